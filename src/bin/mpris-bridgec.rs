@@ -1,19 +1,24 @@
 use std::{
+    cell::OnceCell,
+    collections::HashMap,
     env,
     fs::{self, File, OpenOptions},
     io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
     os::unix::net::UnixStream,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use mpris_bridge::{instance_dir, loop_status, pango_escape, socket_path, take_instance_flag, truncate, SCHEMA_VERSION};
 use serde::Deserialize;
 use serde_json::json;
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct State {
+    schema_version: Option<u32>,
     name: Option<String>,
     title: Option<String>,
     artist: Option<String>,
@@ -22,30 +27,85 @@ struct State {
     length: Option<f64>,
 }
 
-fn runtime_dir() -> String {
-    env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| {
-        let uid = nix::unistd::Uid::current().as_raw();
-        format!("/run/user/{uid}")
-    })
-}
+// Keys the client reads from snapshots and events.
+const STATE_KEYS: [&str; 7] = ["schemaVersion", "name", "title", "artist", "status", "position", "length"];
 
-fn socket_path() -> PathBuf {
-    PathBuf::from(format!("{}/mpris-bridge/mpris-bridge.sock", runtime_dir()))
-}
-fn state_path() -> PathBuf {
-    PathBuf::from(format!("{}/mpris-bridge/state.json", runtime_dir()))
+// The daemon instance this client talks to (--instance), and whether to start it (--autostart).
+struct Daemon {
+    instance: Option<String>,
+    autostart: bool,
+    config: OnceCell<serde_json::Value>, // get-config reply, asked for at most once
+    renames: OnceCell<HashMap<String, String>>, // output.field_names, written key -> standard key
 }
-fn events_path() -> PathBuf {
-    PathBuf::from(format!("{}/mpris-bridge/events.jsonl", runtime_dir()))
+
+impl Daemon {
+    fn new(instance: Option<String>, autostart: bool) -> Self {
+        Self { instance, autostart, config: OnceCell::new(), renames: OnceCell::new() }
+    }
+    // The running daemon's effective config; null if it can't be asked.
+    fn config(&self) -> &serde_json::Value {
+        self.config.get_or_init(|| {
+            send_over_socket(self, &json!({"cmd":"get-config"}).to_string())
+                .ok()
+                .and_then(|reply| serde_json::from_str(reply.trim()).ok())
+                .unwrap_or_default()
+        })
+    }
+    // A snapshot or event as JSON. If one of `keys` is missing, output.field_names may have renamed
+    // it: the daemon is asked for the mapping and the standard names are restored.
+    fn state_value(&self, text: &str, keys: &[&str]) -> Option<serde_json::Value> {
+        let v: serde_json::Value = serde_json::from_str(text.trim()).ok()?;
+        if keys.iter().all(|k| v.get(k).is_some()) {
+            return Some(v);
+        }
+        let renames = self.renames.get_or_init(|| field_renames(self.config()));
+        Some(standard_names(v, renames))
+    }
+    fn parse_state(&self, text: &str) -> Option<State> {
+        serde_json::from_value(self.state_value(text, &STATE_KEYS)?).ok()
+    }
+    fn dir(&self) -> PathBuf {
+        instance_dir(self.instance.as_deref())
+    }
+    fn socket_path(&self) -> PathBuf {
+        socket_path(self.instance.as_deref())
+    }
+    fn state_path(&self) -> PathBuf {
+        self.dir().join("state.json")
+    }
+    fn events_path(&self) -> PathBuf {
+        self.dir().join("events.jsonl")
+    }
 }
 
-fn read_selected_from_state() -> Option<String> {
-    let p = state_path();
+fn read_selected_from_state(daemon: &Daemon) -> Option<String> {
+    let p = daemon.state_path();
     let txt = fs::read_to_string(p).ok()?;
-    let v: serde_json::Value = serde_json::from_str(&txt).ok()?;
+    let v = daemon.state_value(&txt, &["name"])?;
     v.get("name").and_then(|x| x.as_str()).map(|s| s.to_string())
 }
 
+// output.field_names from a get-config reply, inverted.
+fn field_renames(config: &serde_json::Value) -> HashMap<String, String> {
+    config
+        .pointer("/config/output/field_names")
+        .and_then(|x| x.as_object())
+        .into_iter()
+        .flatten()
+        .filter_map(|(standard, written)| Some((written.as_str()?.to_string(), standard.clone())))
+        .collect()
+}
+
+fn standard_names(mut v: serde_json::Value, renames: &HashMap<String, String>) -> serde_json::Value {
+    if let Some(obj) = v.as_object_mut().filter(|_| !renames.is_empty()) {
+        *obj = std::mem::take(obj)
+            .into_iter()
+            .map(|(k, val)| (renames.get(&k).cloned().unwrap_or(k), val))
+            .collect();
+    }
+    v
+}
+
 fn playerctl_exec(maybe_player: Option<String>, args: &[&str]) {
     let mut cmd = Command::new("playerctl");
     if let Some(p) = maybe_player {
@@ -57,62 +117,289 @@ fn playerctl_exec(maybe_player: Option<String>, args: &[&str]) {
         .status();
 }
 
-fn send_over_socket(payload: &str) -> std::io::Result<()> {
-    let mut stream = UnixStream::connect(socket_path())?;
+fn send_over_socket(daemon: &Daemon, payload: &str) -> std::io::Result<String> {
+    let mut stream = match UnixStream::connect(daemon.socket_path()) {
+        Ok(s) => s,
+        Err(_) if daemon.autostart => autostart_daemon(daemon)?,
+        Err(e) => return Err(e),
+    };
     stream.write_all(payload.as_bytes())?;
     stream.write_all(b"\n")?;
     let mut reader = BufReader::new(stream);
     let mut line = String::new();
     let _ = reader.read_line(&mut line);
-    Ok(())
+    Ok(line)
 }
 
-fn usage() {
-    eprintln!(
-        "{}",
-        r#"Usage:
+const AUTOSTART_WAIT: Duration = Duration::from_secs(3);
+// Only for a lock without a readable PID; well past any startup (seed + first snapshot)
+const AUTOSTART_LOCK_STALE: Duration = Duration::from_secs(60);
+
+// The lock holds the started daemon's PID: it's stale once that process is gone (a failed
+// start), however long the daemon takes to bind its socket.
+fn autostart_lock_stale(lock: &Path) -> bool {
+    match fs::read_to_string(lock).ok().and_then(|t| t.trim().parse::<u32>().ok()) {
+        Some(pid) => !Path::new(&format!("/proc/{pid}")).exists(),
+        None => fs::metadata(lock)
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t.elapsed().unwrap_or_default() > AUTOSTART_LOCK_STALE),
+    }
+}
+
+// --autostart: start mpris-bridged (detached) and wait for its socket. A lock file created with
+// O_EXCL keeps concurrent clients (e.g. several media key presses) from each starting one; the
+// losers just wait for the socket.
+fn autostart_daemon(daemon: &Daemon) -> std::io::Result<UnixStream> {
+    let dir = daemon.dir();
+    fs::create_dir_all(&dir)?;
+    let lock = dir.join("autostart.lock");
+    if autostart_lock_stale(&lock) {
+        let _ = fs::remove_file(&lock);
+    }
+    if let Ok(mut lock_file) = OpenOptions::new().write(true).create_new(true).open(&lock) {
+        use std::os::unix::process::CommandExt;
+        let mut cmd = Command::new("mpris-bridged");
+        if let Some(instance) = &daemon.instance {
+            cmd.arg("--instance").arg(instance);
+        }
+        let spawned = cmd
+            .env_remove("MPRIS_BRIDGE_AUTOSTART")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0) // not killed with the client's terminal/session
+            .spawn();
+        match spawned {
+            Ok(child) => {
+                let _ = write!(lock_file, "{}", child.id());
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&lock);
+                return Err(e);
+            }
+        }
+    }
+    let deadline = Instant::now() + AUTOSTART_WAIT;
+    let res = loop {
+        match UnixStream::connect(daemon.socket_path()) {
+            Ok(s) => break Ok(s),
+            Err(e) if Instant::now() >= deadline => break Err(e),
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    };
+    if res.is_ok() {
+        let _ = fs::remove_file(&lock);
+    }
+    res
+}
+
+// Daemon-only commands: no playerctl fallback, exit non-zero on failure.
+fn send_or_exit(daemon: &Daemon, payload: &str) -> serde_json::Value {
+    let reply = match send_over_socket(daemon, payload) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("mpris-bridgec: daemon socket unavailable: {e}");
+            std::process::exit(1);
+        }
+    };
+    let v: serde_json::Value = serde_json::from_str(reply.trim()).unwrap_or_default();
+    if v.get("ok").and_then(serde_json::Value::as_bool) != Some(true) {
+        eprintln!("mpris-bridgec: command failed: {}", reply.trim());
+        std::process::exit(1);
+    }
+    v
+}
+
+const USAGE: &str = r#"Usage:
   mpris-bridgec play-pause [--player <name>]
   mpris-bridgec next [--player <name>]
   mpris-bridgec previous [--player <name>]
-  mpris-bridgec seek <offset-seconds> [--player <name>]
-  mpris-bridgec set-position <seconds> [--player <name>]
-  mpris-bridgec watch [--format <fmt>] [--truncate <n>] [--pango-escape]
+  mpris-bridgec smart-previous [<threshold-secs>] [--player <name>]   (restart past 3s, else previous)
+  mpris-bridgec seek <[+|-]seconds|[+|-]m:ss> [--player <name>]
+  mpris-bridgec seek-forward | seek-backward [--player <name>]   (step: selection.seek_step_secs)
+  mpris-bridgec set-position <seconds|m:ss|h:mm:ss> [--player <name>]
+  mpris-bridgec loop <none|track|playlist> [--player <name>]
+  mpris-bridgec shuffle <on|off> [--player <name>]
+  mpris-bridgec caps [--player <name>]    (capability flags as JSON: can_next, can_prev, can_seek, ...)
+  mpris-bridgec fullscreen [--player <name>]   (toggle; needs MPRIS CanSetFullscreen)
+  mpris-bridgec pin <player-prefix> <seconds>
+  mpris-bridgec toggle-players <prefix-a> <prefix-b>   (switch to the other one; held for 10s)
+  mpris-bridgec clear-last                (forget the remember_last player)
+  mpris-bridgec ping                      (health check: exit 0 if the daemon answers over IPC)
+  mpris-bridgec playlists [--player <name>]
+  mpris-bridgec activate-playlist <id> [--player <name>]
+  mpris-bridgec meta [--player <name>]
+  mpris-bridgec config                    (effective daemon config, paths expanded)
+  mpris-bridgec freeze | unfreeze         (hold the displayed state, e.g. for a demo)
+  mpris-bridgec set-truncation [--title <n>] [--artist <n>]   (until the daemon restarts)
+  mpris-bridgec wait [--status <playing|paused|stopped>] [--title-changed] [--timeout <secs>] [--player <name>]
+  mpris-bridgec get <field> [--default <value>]   (one state.json field, e.g. title, status, position)
+  mpris-bridgec log [-n <count>]          (last events, pretty-printed; default 20; without an events
+                                          file they come from output.events_memory_ring)
+  mpris-bridgec watch [--format <fmt>] [--truncate <n> [--word-boundary]] [--pango-escape]
+                      [--only <player-prefix> [--placeholder <text>]] [--dedup]
+                      [--polybar [--click-left <cmd>] [--click-middle <cmd>] [--click-right <cmd>]]
+                      [--bar-chars <filled><empty>]
+
+--player-identity <identity>  Target the player whose MPRIS Identity matches (e.g. "Mozilla Firefox");
+                              resolved by the daemon, so it must be running
+--instance <name>             Talk to the daemon started with the same --instance (or $MPRIS_BRIDGE_INSTANCE)
+--autostart                   If the daemon isn't running, start mpris-bridged and retry over its socket
+                              (also MPRIS_BRIDGE_AUTOSTART=1)
+--no-fallback                 Control commands go through the daemon only (no direct playerctl);
+                              they fail with a non-zero exit if the daemon isn't running
 
 watch defaults:
   --format "{artist}{sep}{title}"
   where sep = " - " if both artist & title are non-empty, else ""
+  {bar:N} is an N-character progress bar (--bar-chars, default "▮▯"), {percent} the progress 0-100;
+  both are empty/unfilled for streams of unknown length
 
+wait             Block until an event matches every given condition (exit 0), or --timeout passes (exit 1)
+
+--word-boundary  With --truncate, cut at the last whole word ("The Dark…" instead of "The Dark Si…")
+--only           Show only events from players whose name starts with the prefix;
+                 other events print the placeholder (default: empty line)
+get              Field names are the camelCase JSON keys; exits 1 if the field is missing or empty
+                 (unless --default is given)
+--dedup          Don't print a label identical to the previous line (e.g. position-only updates)
+--polybar        Wrap the label in polybar click actions for the shown player:
+                 left = play-pause, middle = previous, right = next; `{player}` in --click-* is
+                 replaced by the player name
 --pango-escape   Escape Pango markup: & < > ' " → &amp; &lt; &gt; &apos; &quot;
-"#
-    );
+"#;
+
+fn usage() {
+    eprintln!("{USAGE}");
 }
 
 fn main() {
     let mut args: Vec<String> = env::args().skip(1).collect();
+    let instance = take_instance_flag(&mut args).unwrap_or_else(|e| {
+        eprintln!("mpris-bridgec: {e}");
+        std::process::exit(2);
+    });
     if args.is_empty() {
         usage();
         std::process::exit(2);
     }
 
-    // общий флаг --player для команд управления
+    // общий флаг --player (или --player-identity) для команд управления
     let mut player_arg: Option<String> = None;
+    let mut identity_arg: Option<String> = None;
+    let mut no_fallback = false;
+    let mut autostart = env::var("MPRIS_BRIDGE_AUTOSTART").is_ok_and(|v| v == "1");
     let mut i = 0;
     while i < args.len() {
-        if args[i] == "--player" && i + 1 < args.len() {
+        if args[i] == "--no-fallback" {
+            no_fallback = true;
+            args.remove(i);
+        } else if args[i] == "--autostart" {
+            autostart = true;
+            args.remove(i);
+        } else if args[i] == "--player" && i + 1 < args.len() {
             player_arg = Some(args.remove(i + 1));
             args.remove(i);
+        } else if args[i] == "--player-identity" && i + 1 < args.len() {
+            identity_arg = Some(args.remove(i + 1));
+            args.remove(i);
         } else {
             i += 1;
         }
     }
+    let daemon = Daemon::new(instance, autostart);
+    // Identity → bus name needs D-Bus, so the daemon resolves it.
+    if let Some(identity) = identity_arg {
+        let v = send_or_exit(&daemon, &json!({"cmd":"resolve-identity","identity":identity}).to_string());
+        player_arg = v.get("player").and_then(|x| x.as_str()).map(str::to_string);
+    }
 
     let cmd = args.remove(0);
     match cmd.as_str() {
-        "play-pause" | "next" | "previous" | "seek" | "set-position" => {
-            run_control(cmd, player_arg, args);
+        "play-pause" | "next" | "previous" | "seek" | "set-position" | "loop" | "shuffle" => {
+            run_control(&daemon, cmd, player_arg, args, no_fallback);
+        }
+        "smart-previous" => {
+            let threshold: Option<f64> = args.first().map(|a| {
+                a.parse().unwrap_or_else(|_| {
+                    usage();
+                    std::process::exit(2);
+                })
+            });
+            send_or_exit(&daemon, &json!({"cmd":"smart-previous","threshold_secs":threshold,"player":player_arg}).to_string());
+        }
+        "seek-forward" | "seek-backward" => {
+            let forward = cmd == "seek-forward";
+            send_or_exit(&daemon, &json!({"cmd":"seek-step","forward":forward,"player":player_arg}).to_string());
+        }
+        "caps" => {
+            let mut v = send_or_exit(&daemon, &json!({"cmd":"caps","player":player_arg}).to_string());
+            if let Some(obj) = v.as_object_mut() {
+                obj.remove("ok");
+            }
+            println!("{}", serde_json::to_string_pretty(&v).unwrap_or_default());
+        }
+        "fullscreen" => {
+            send_or_exit(&daemon, &json!({"cmd":"toggle-fullscreen","player":player_arg}).to_string());
+        }
+        "pin" => {
+            run_pin(&daemon, &args);
+        }
+        "toggle-players" => {
+            let [a, b] = args.as_slice() else {
+                usage();
+                std::process::exit(2);
+            };
+            send_or_exit(&daemon, &json!({"cmd":"toggle-players","a":a,"b":b}).to_string());
+        }
+        "clear-last" => {
+            send_or_exit(&daemon, &json!({"cmd":"clear-last"}).to_string());
+        }
+        "ping" => {
+            let v = send_or_exit(&daemon, &json!({"cmd":"ping"}).to_string());
+            if v.get("pong").and_then(serde_json::Value::as_bool) != Some(true) {
+                eprintln!("mpris-bridgec: unexpected ping reply: {v}");
+                std::process::exit(1);
+            }
+            println!("pong");
+        }
+        "playlists" => {
+            run_playlists(&daemon, player_arg);
+        }
+        "activate-playlist" => {
+            let Some(id) = args.first() else {
+                usage();
+                std::process::exit(2);
+            };
+            send_or_exit(&daemon, &json!({"cmd":"activate-playlist","id":id,"player":player_arg}).to_string());
+        }
+        "config" => {
+            let v = send_or_exit(&daemon, &json!({"cmd":"get-config"}).to_string());
+            let cfg = v.get("config").cloned().unwrap_or_default();
+            println!("{}", serde_json::to_string_pretty(&cfg).unwrap_or_default());
+        }
+        "freeze" | "unfreeze" => {
+            send_or_exit(&daemon, &json!({"cmd":"freeze","on":cmd == "freeze"}).to_string());
+        }
+        "set-truncation" => {
+            run_set_truncation(&daemon, &args);
+        }
+        "meta" => {
+            let v = send_or_exit(&daemon, &json!({"cmd":"meta","player":player_arg}).to_string());
+            let meta = v.get("metadata").cloned().unwrap_or_default();
+            println!("{}", serde_json::to_string_pretty(&meta).unwrap_or_default());
+        }
+        "wait" => {
+            run_wait(&daemon, &args, player_arg);
+        }
+        "get" => {
+            run_get(&daemon, &args);
+        }
+        "log" => {
+            run_log(&daemon, &args);
         }
         "watch" => {
-            run_watch(args);
+            run_watch(&daemon, args);
         }
         _ => {
             usage();
@@ -121,116 +408,464 @@ fn main() {
     }
 }
 
-fn resolve_player(explicit: Option<String>) -> Option<String> {
+fn resolve_player(daemon: &Daemon, explicit: Option<String>) -> Option<String> {
     if explicit.is_some() {
         return explicit;
     }
-    if let Some(sel) = read_selected_from_state() {
+    if let Some(sel) = read_selected_from_state(daemon) {
         return Some(sel);
     }
     None
 }
 
-fn run_control(cmd: String, player_arg: Option<String>, mut args: Vec<String>) {
-    let mut socket_payload = None;
-    let mut fallback: Option<(Option<String>, Vec<String>)> = None;
-
-    match cmd.as_str() {
-        "play-pause" => {
-            socket_payload = Some(json!({"cmd":"play-pause","player":player_arg}).to_string());
-            fallback = Some((resolve_player(player_arg), vec!["play-pause".into()]));
+fn run_control(daemon: &Daemon, cmd: String, player_arg: Option<String>, args: Vec<String>, no_fallback: bool) {
+    let (socket_payload, fallback): (String, (Option<String>, Vec<String>)) = match cmd.as_str() {
+        "play-pause" => (
+            json!({"cmd":"play-pause","player":player_arg}).to_string(),
+            (resolve_player(daemon, player_arg), vec!["play-pause".into()]),
+        ),
+        "next" => (
+            json!({"cmd":"next","player":player_arg}).to_string(),
+            (resolve_player(daemon, player_arg), vec!["next".into()]),
+        ),
+        "previous" => (
+            json!({"cmd":"previous","player":player_arg}).to_string(),
+            (resolve_player(daemon, player_arg), vec!["previous".into()]),
+        ),
+        "seek" => {
+            let Some(off) = args.first().and_then(|a| parse_offset(a)) else {
+                usage();
+                std::process::exit(2);
+            };
+            let sec = off.abs().round() as i64;
+            let s = if off >= 0.0 { format!("{sec}+") } else { format!("{sec}-") };
+            (
+                json!({"cmd":"seek","offset":off,"player":player_arg}).to_string(),
+                (resolve_player(daemon, player_arg), vec!["position".into(), s]),
+            )
         }
-        "next" => {
-            socket_payload = Some(json!({"cmd":"next","player":player_arg}).to_string());
-            fallback = Some((resolve_player(player_arg), vec!["next".into()]));
+        "set-position" => {
+            let Some(pos) = args.first().and_then(|a| parse_time(a)) else {
+                usage();
+                std::process::exit(2);
+            };
+            let s = format!("{}", pos.round() as i64);
+            (
+                json!({"cmd":"set-position","position":pos,"player":player_arg}).to_string(),
+                (resolve_player(daemon, player_arg), vec!["position".into(), s]),
+            )
         }
-        "previous" => {
-            socket_payload = Some(json!({"cmd":"previous","player":player_arg}).to_string());
-            fallback = Some((resolve_player(player_arg), vec!["previous".into()]));
+        "loop" => {
+            let Some(status) = args.first().and_then(|m| loop_status(m)) else {
+                eprintln!("mpris-bridgec: loop expects none|track|playlist");
+                std::process::exit(2);
+            };
+            (
+                json!({"cmd":"set-loop","mode":args[0],"player":player_arg}).to_string(),
+                (resolve_player(daemon, player_arg), vec!["loop".into(), status.into()]),
+            )
         }
-        "seek" => {
-            if args.is_empty() {
+        "shuffle" => {
+            let on = match args.first().map(String::as_str) {
+                Some("on") => true,
+                Some("off") => false,
+                _ => {
+                    eprintln!("mpris-bridgec: shuffle expects on|off");
+                    std::process::exit(2);
+                }
+            };
+            (
+                json!({"cmd":"set-shuffle","on":on,"player":player_arg}).to_string(),
+                (resolve_player(daemon, player_arg), vec!["shuffle".into(), if on { "On" } else { "Off" }.into()]),
+            )
+        }
+        _ => unreachable!(),
+    };
+
+    if no_fallback {
+        send_or_exit(daemon, &socket_payload);
+        return;
+    }
+    if send_over_socket(daemon, &socket_payload).is_ok() {
+        return;
+    }
+    let (maybe_player, argv) = fallback;
+    let argv_ref: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
+    playerctl_exec(maybe_player, &argv_ref);
+}
+
+// Seconds from "90", "1:30" or "1:05:00" (fractional seconds allowed in the last field).
+fn parse_time(s: &str) -> Option<f64> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 || parts.iter().any(|p| p.is_empty() || p.starts_with(['+', '-'])) {
+        return None;
+    }
+    let (secs, rest) = parts.split_last()?;
+    let secs: f64 = secs.parse().ok().filter(|v: &f64| v.is_finite())?;
+    if !rest.is_empty() && secs >= 60.0 {
+        return None;
+    }
+    let mut total = secs;
+    for (i, p) in rest.iter().rev().enumerate() {
+        let v: u64 = p.parse().ok()?;
+        // minutes under an hour field must stay below 60
+        if i == 0 && rest.len() == 2 && v >= 60 {
+            return None;
+        }
+        total += v as f64 * 60f64.powi(i as i32 + 1);
+    }
+    Some(total)
+}
+
+// Signed seek offset: "5", "-10", "+1:30".
+fn parse_offset(s: &str) -> Option<f64> {
+    match s.strip_prefix('-') {
+        Some(rest) => parse_time(rest).map(|v| -v),
+        None => parse_time(s.strip_prefix('+').unwrap_or(s)),
+    }
+}
+
+fn run_pin(daemon: &Daemon, args: &[String]) {
+    let (Some(player), Some(ttl_secs)) = (args.first(), args.get(1).and_then(|s| s.parse::<u64>().ok())) else {
+        usage();
+        std::process::exit(2);
+    };
+    send_or_exit(daemon, &json!({"cmd":"pin","player":player,"ttl_secs":ttl_secs}).to_string());
+}
+
+fn run_set_truncation(daemon: &Daemon, args: &[String]) {
+    let mut title: Option<usize> = None;
+    let mut artist: Option<usize> = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        let n = it.next().and_then(|s| s.parse().ok());
+        match (a.as_str(), n) {
+            ("--title", Some(n)) => title = Some(n),
+            ("--artist", Some(n)) => artist = Some(n),
+            _ => {
                 usage();
                 std::process::exit(2);
             }
-            let off = args[0].parse::<f64>().unwrap_or(0.0);
-            socket_payload = Some(json!({"cmd":"seek","offset":off,"player":player_arg}).to_string());
+        }
+    }
+    if title.is_none() && artist.is_none() {
+        usage();
+        std::process::exit(2);
+    }
+    send_or_exit(daemon, &json!({"cmd":"set-truncation","title":title,"artist":artist}).to_string());
+}
 
-            let sec = off.abs().round() as i64;
-            let s = if off >= 0.0 { format!("{sec}+") } else { format!("{sec}-") };
-            fallback = Some((resolve_player(player_arg), vec!["position".into(), s]));
+// One playlist per line: "<id>\t<name>", the active one suffixed with "\t*".
+fn run_playlists(daemon: &Daemon, player_arg: Option<String>) {
+    let v = send_or_exit(daemon, &json!({"cmd":"playlists","player":player_arg}).to_string());
+    let active = v.pointer("/active/id").and_then(|x| x.as_str());
+    for pl in v.get("playlists").and_then(|x| x.as_array()).into_iter().flatten() {
+        let id = pl.get("id").and_then(|x| x.as_str()).unwrap_or("");
+        let name = pl.get("name").and_then(|x| x.as_str()).unwrap_or("");
+        if Some(id) == active {
+            println!("{id}\t{name}\t*");
+        } else {
+            println!("{id}\t{name}");
         }
-        "set-position" => {
-            if args.is_empty() {
+    }
+}
+
+// Exit 0 once an event matches every given condition, 1 on timeout.
+fn run_wait(daemon: &Daemon, args: &[String], player_arg: Option<String>) {
+    let mut status: Option<String> = None;
+    let mut title_changed = false;
+    let mut timeout: Option<f64> = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--status" => status = it.next().map(|s| s.to_lowercase()),
+            "--title-changed" => title_changed = true,
+            "--timeout" => timeout = it.next().and_then(|s| s.parse().ok()),
+            _ => {
                 usage();
                 std::process::exit(2);
             }
-            let pos = args[0].parse::<f64>().unwrap_or(0.0);
-            socket_payload = Some(json!({"cmd":"set-position","position":pos,"player":player_arg}).to_string());
+        }
+    }
 
-            let s = format!("{}", pos.round() as i64);
-            fallback = Some((resolve_player(player_arg), vec!["position".into(), s]));
+    let current = fs::read_to_string(daemon.state_path()).ok().and_then(|t| daemon.parse_state(&t));
+    let matches = |st: &State, initial_title: Option<&str>| {
+        player_arg.as_deref().is_none_or(|p| st.name.as_deref() == Some(p))
+            && status.as_deref().is_none_or(|s| st.status.as_deref().is_some_and(|x| x.eq_ignore_ascii_case(s)))
+            && (!title_changed || st.title.as_deref() != initial_title)
+    };
+    let initial_title = current
+        .as_ref()
+        .filter(|st| player_arg.is_none() || st.name == player_arg)
+        .and_then(|st| st.title.clone());
+    // Already there (e.g. "wait --status paused" when nothing plays): no event will come.
+    if !title_changed && current.as_ref().is_some_and(|st| matches(st, None)) {
+        return;
+    }
+
+    let deadline = timeout.map(|t| Instant::now() + Duration::from_secs_f64(t.max(0.0)));
+    if !tail_events(daemon, deadline, |st| matches(&st, initial_title.as_deref())) {
+        eprintln!("mpris-bridgec: wait timed out");
+        std::process::exit(1);
+    }
+}
+
+fn run_get(daemon: &Daemon, args: &[String]) {
+    let (field, default) = match args {
+        [field] => (field, None),
+        [field, flag, v] if flag == "--default" => (field, Some(v)),
+        _ => {
+            usage();
+            std::process::exit(2);
+        }
+    };
+    let state = fs::read_to_string(daemon.state_path())
+        .ok()
+        .and_then(|t| daemon.state_value(&t, &[field]))
+        .unwrap_or_default();
+    match field_value(&state, field).or_else(|| default.cloned()) {
+        Some(v) => println!("{v}"),
+        None => std::process::exit(1),
+    }
+}
+
+// Strings print raw, everything else as JSON; null and "" count as missing.
+fn field_value(state: &serde_json::Value, field: &str) -> Option<String> {
+    match state.get(field)? {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) if s.is_empty() => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        v => Some(v.to_string()),
+    }
+}
+
+fn run_log(daemon: &Daemon, args: &[String]) {
+    let count = match args {
+        [] => 20,
+        [flag, n] if flag == "-n" => n.parse().unwrap_or_else(|_| {
+            usage();
+            std::process::exit(2);
+        }),
+        _ => {
+            usage();
+            std::process::exit(2);
+        }
+    };
+    // No events file (output.events_path = ""): ask the daemon's in-memory ring instead.
+    if events_disabled(daemon) || !daemon.events_path().exists() {
+        let v = send_or_exit(daemon, &json!({"cmd":"recent-events","n":count}).to_string());
+        for ev in v.get("events").and_then(|x| x.as_array()).into_iter().flatten() {
+            println!("{}", serde_json::to_string_pretty(ev).unwrap_or_default());
+        }
+        return;
+    }
+    for line in last_events(&daemon.events_path(), count) {
+        match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(v) => println!("{}", serde_json::to_string_pretty(&v).unwrap_or(line)),
+            Err(_) => println!("{line}"),
         }
-        _ => unreachable!(),
     }
+}
+
+// Last `count` lines of the events file; if it was just rotated (logrotate-style `events.jsonl.1`),
+// the rest comes from the previous file.
+fn last_events(path: &Path, count: usize) -> Vec<String> {
+    let mut lines = tail_lines(path, count);
+    if lines.len() < count {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        let mut older = tail_lines(Path::new(&rotated), count - lines.len());
+        older.append(&mut lines);
+        lines = older;
+    }
+    lines
+}
 
-    if let Some(pay) = socket_payload {
-        if send_over_socket(&pay).is_ok() {
-            return;
+// Reads backwards in chunks so a long-lived events file isn't loaded whole.
+fn tail_lines(path: &Path, count: usize) -> Vec<String> {
+    const CHUNK: u64 = 8192;
+    let Ok(mut file) = File::open(path) else {
+        return Vec::new();
+    };
+    let Ok(len) = file.seek(SeekFrom::End(0)) else {
+        return Vec::new();
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    let mut pos = len;
+    // count + 1 newlines guarantee `count` complete lines (the last one ends with '\n')
+    while pos > 0 && buf.iter().filter(|b| **b == b'\n').count() <= count {
+        let step = CHUNK.min(pos);
+        pos -= step;
+        let mut chunk = vec![0; step as usize];
+        if file.seek(SeekFrom::Start(pos)).is_err() || file.read_exact(&mut chunk).is_err() {
+            return Vec::new();
         }
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
     }
-    if let Some((maybe_player, argv)) = fallback {
-        let argv_ref: Vec<&str> = argv.iter().map(|s| s.as_str()).collect();
-        playerctl_exec(maybe_player, &argv_ref);
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines: Vec<String> = text.lines().filter(|l| !l.trim().is_empty()).map(str::to_string).collect();
+    if pos > 0 && !lines.is_empty() {
+        lines.remove(0); // partial line at the chunk boundary
     }
+    let skip = lines.len().saturating_sub(count);
+    lines.split_off(skip)
 }
 
-fn run_watch(mut args: Vec<String>) {
-    // флаги: --format, --truncate, --pango-escape
-    let mut format: Option<String> = None;
-    let mut truncate: Option<usize> = None;
-    let mut pango_escape = false;
+struct WatchOpts {
+    format: Option<String>,
+    truncate: Option<usize>,
+    word_boundary: bool, // --truncate cuts at the last whole word
+    pango_escape: bool,
+    only: Option<String>, // print only events whose `name` starts with this prefix
+    placeholder: String, // printed instead of a filtered-out event
+    dedup: bool, // skip a label identical to the previous line
+    polybar: Option<PolybarClicks>, // wrap the label in polybar click actions
+    bar_chars: (char, char), // (filled, empty) for {bar:N}
+}
+
+// Commands behind the polybar click actions; `{player}` is replaced by the shown player.
+struct PolybarClicks {
+    left: String,
+    middle: String,
+    right: String,
+}
+
+fn run_watch(daemon: &Daemon, mut args: Vec<String>) {
+    // флаги: --format, --truncate, --pango-escape, --only, --placeholder
+    let mut opts = WatchOpts {
+        format: None,
+        truncate: None,
+        word_boundary: false,
+        pango_escape: false,
+        only: None,
+        placeholder: String::new(),
+        dedup: false,
+        polybar: None,
+        bar_chars: ('▮', '▯'),
+    };
+    let mut clicks = PolybarClicks {
+        left: "mpris-bridgec play-pause --player {player}".into(),
+        middle: "mpris-bridgec previous --player {player}".into(),
+        right: "mpris-bridgec next --player {player}".into(),
+    };
+    let mut polybar = false;
 
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
             "--format" if i + 1 < args.len() => {
-                format = Some(args.remove(i + 1));
+                opts.format = Some(args.remove(i + 1));
                 args.remove(i);
             }
             "--truncate" if i + 1 < args.len() => {
-                truncate = args[i + 1].parse::<usize>().ok();
+                opts.truncate = args[i + 1].parse::<usize>().ok();
                 args.drain(i..=i + 1);
             }
             "--pango-escape" => {
-                pango_escape = true;
+                opts.pango_escape = true;
+                args.remove(i);
+            }
+            "--word-boundary" => {
+                opts.word_boundary = true;
+                args.remove(i);
+            }
+            "--dedup" => {
+                opts.dedup = true;
+                args.remove(i);
+            }
+            "--only" if i + 1 < args.len() => {
+                opts.only = Some(args.remove(i + 1));
+                args.remove(i);
+            }
+            "--placeholder" if i + 1 < args.len() => {
+                opts.placeholder = args.remove(i + 1);
+                args.remove(i);
+            }
+            "--polybar" => {
+                polybar = true;
                 args.remove(i);
             }
+            "--bar-chars" if i + 1 < args.len() => {
+                let glyphs: Vec<char> = args[i + 1].chars().collect();
+                let [filled, empty] = glyphs[..] else {
+                    eprintln!("mpris-bridgec: --bar-chars expects two characters, e.g. \"#-\"");
+                    std::process::exit(2);
+                };
+                opts.bar_chars = (filled, empty);
+                args.drain(i..=i + 1);
+            }
+            "--click-left" | "--click-middle" | "--click-right" if i + 1 < args.len() => {
+                let cmd = args.remove(i + 1);
+                match args.remove(i).as_str() {
+                    "--click-left" => clicks.left = cmd,
+                    "--click-middle" => clicks.middle = cmd,
+                    _ => clicks.right = cmd,
+                }
+            }
             _ => i += 1,
         }
     }
+    if polybar {
+        opts.polybar = Some(clicks);
+    }
 
     // Выводим текущий снапшот
-    if let Some(line) = compute_label_from_snapshot(format.as_deref(), truncate, pango_escape) {
+    let first = compute_label_from_snapshot(daemon, &opts);
+    if let Some(line) = &first {
         println!("{line}");
         std::io::stdout().flush().ok();
     }
 
     // Читаем events.jsonl и печатаем обновления
-    follow_events_and_print(format.as_deref(), truncate, pango_escape);
+    follow_events_and_print(daemon, &opts, first);
 }
 
-fn compute_label_from_snapshot(fmt: Option<&str>, trunc: Option<usize>, pango: bool) -> Option<String> {
-    let p = state_path();
+fn compute_label_from_snapshot(daemon: &Daemon, opts: &WatchOpts) -> Option<String> {
+    let p = daemon.state_path();
     let txt = fs::read_to_string(p).ok()?;
-    let v: serde_json::Value = serde_json::from_str(&txt).ok()?;
-    let artist = v.get("artist").and_then(|x| x.as_str()).unwrap_or("");
-    let title = v.get("title").and_then(|x| x.as_str()).unwrap_or("");
-    let line = format_label(artist, title, fmt, trunc);
-    Some(if pango { pango_escape(&line) } else { line })
+    let st = daemon.parse_state(&txt)?;
+    Some(render_label(&st, opts))
 }
 
-fn format_label(artist: &str, title: &str, fmt: Option<&str>, trunc: Option<usize>) -> String {
+fn render_label(st: &State, opts: &WatchOpts) -> String {
+    if let Some(prefix) = &opts.only {
+        if !st.name.as_deref().unwrap_or("").starts_with(prefix.as_str()) {
+            return opts.placeholder.clone();
+        }
+    }
+    let artist = st.artist.as_deref().unwrap_or("");
+    let title = st.title.as_deref().unwrap_or("");
+    let fmt = opts
+        .format
+        .as_deref()
+        .map(|f| progress_tokens(f, st.position.unwrap_or(0.0), st.length.unwrap_or(0.0), opts.bar_chars));
+    let line = format_label(artist, title, fmt.as_deref(), opts.truncate, opts.word_boundary);
+    let line = if opts.pango_escape { pango_escape(&line) } else { line };
+    match &opts.polybar {
+        Some(clicks) => polybar_actions(&line, st.name.as_deref().unwrap_or(""), clicks),
+        None => line,
+    }
+}
+
+// %{A1:cmd:}label%{A}: polybar runs `cmd` on click. ':' in commands and '%' in the label are escaped.
+fn polybar_actions(label: &str, player: &str, clicks: &PolybarClicks) -> String {
+    if label.is_empty() || player.is_empty() {
+        return label.replace('%', "%%");
+    }
+    let action = |button: u8, cmd: &str| {
+        format!("%{{A{button}:{}:}}", cmd.replace("{player}", player).replace(':', "\\:"))
+    };
+    format!(
+        "{}{}{}{}%{{A}}%{{A}}%{{A}}",
+        action(1, &clicks.left),
+        action(2, &clicks.middle),
+        action(3, &clicks.right),
+        label.replace('%', "%%")
+    )
+}
+
+fn format_label(artist: &str, title: &str, fmt: Option<&str>, trunc: Option<usize>, word_boundary: bool) -> String {
     let (artist_s, title_s) = (artist.to_string(), title.to_string());
     let sep = if !artist_s.is_empty() && !title_s.is_empty() { " - " } else { "" };
     let mut out = if let Some(f) = fmt {
@@ -239,27 +874,67 @@ fn format_label(artist: &str, title: &str, fmt: Option<&str>, trunc: Option<usiz
         format!("{}{}{}", artist_s, sep, title_s)
     };
     if let Some(n) = trunc {
-        if out.chars().count() > n {
-            out = out.chars().take(n.saturating_sub(1)).collect::<String>() + "…";
-        }
+        out = truncate(&out, n, word_boundary);
     }
     out
 }
 
-fn pango_escape(s: &str) -> String {
-    // порядок важен: сначала & затем остальные
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('\'', "&apos;")
-        .replace('"', "&quot;")
+// {bar:N}: N-glyph progress bar; {percent}: 0-100. Unknown length (streams): an all-empty bar and "".
+fn progress_tokens(fmt: &str, position: f64, length: f64, (filled, empty): (char, char)) -> String {
+    let ratio = (length > 0.0).then(|| (position / length).clamp(0.0, 1.0));
+    let mut out = fmt.replace("{percent}", &ratio.map(|r| format!("{:.0}", r * 100.0)).unwrap_or_default());
+    let mut from = 0;
+    while let Some(start) = out[from..].find("{bar:").map(|s| from + s) {
+        let Some(end) = out[start..].find('}').map(|e| start + e) else { break };
+        // Not a width ({bar:x}): left as written, later bars still render
+        let Ok(width) = out[start + 5..end].parse::<usize>() else {
+            from = start + 5;
+            continue;
+        };
+        let full = ratio.map_or(0, |r| ((r * width as f64).round() as usize).min(width));
+        let bar: String = std::iter::repeat_n(filled, full).chain(std::iter::repeat_n(empty, width - full)).collect();
+        out.replace_range(start..=end, &bar);
+        from = start + bar.len();
+    }
+    out
+}
+
+fn follow_events_and_print(daemon: &Daemon, opts: &WatchOpts, mut last: Option<String>) {
+    tail_events(daemon, None, |st| {
+        let label = render_label(&st, opts);
+        if opts.dedup && last.as_ref() == Some(&label) {
+            return false;
+        }
+        println!("{label}");
+        let _ = std::io::stdout().flush();
+        last = Some(label);
+        false
+    });
+}
+
+// output.events_path = "" in the running daemon's config; false if it can't be asked.
+fn events_disabled(daemon: &Daemon) -> bool {
+    config_disables_events(daemon.config())
 }
 
-fn follow_events_and_print(fmt: Option<&str>, trunc: Option<usize>, pango: bool) {
-    let path = events_path();
-    let _ = OpenOptions::new().create(true).append(true).open(&path);
+fn config_disables_events(reply: &serde_json::Value) -> bool {
+    reply.pointer("/config/output/events_path").and_then(|x| x.as_str()) == Some("")
+}
+
+// Feed each new event to `on_state` until it returns true (→ true) or `deadline` passes (→ false).
+// Without an events file the daemon's `subscribe` stream carries the same states.
+fn tail_events(daemon: &Daemon, deadline: Option<Instant>, mut on_state: impl FnMut(State) -> bool) -> bool {
+    let expired = || deadline.is_some_and(|d| Instant::now() >= d);
+    if events_disabled(daemon) {
+        return tail_subscription(daemon, &expired, on_state);
+    }
+    let path = daemon.events_path();
+    let mut warned = false;
 
     loop {
+        if expired() {
+            return false;
+        }
         let file = match File::open(&path) {
             Ok(f) => f,
             Err(_) => {
@@ -272,6 +947,9 @@ fn follow_events_and_print(fmt: Option<&str>, trunc: Option<usize>, pango: bool)
 
         let mut line = String::new();
         loop {
+            if expired() {
+                return false;
+            }
             line.clear();
             match reader.read_line(&mut line) {
                 Ok(0) => {
@@ -279,15 +957,8 @@ fn follow_events_and_print(fmt: Option<&str>, trunc: Option<usize>, pango: bool)
                     continue;
                 }
                 Ok(_) => {
-                    if let Ok(v) = serde_json::from_str::<serde_json::Value>(line.trim()) {
-                        let artist = v.get("artist").and_then(|x| x.as_str()).unwrap_or("");
-                        let title = v.get("title").and_then(|x| x.as_str()).unwrap_or("");
-                        let mut out = format_label(artist, title, fmt, trunc);
-                        if pango {
-                            out = pango_escape(&out);
-                        }
-                        println!("{out}");
-                        let _ = std::io::stdout().flush();
+                    if handle_event_line(daemon, &line, &mut warned, &mut on_state) {
+                        return true;
                     }
                 }
                 Err(_) => {
@@ -296,4 +967,164 @@ fn follow_events_and_print(fmt: Option<&str>, trunc: Option<usize>, pango: bool)
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn handle_event_line(daemon: &Daemon, line: &str, warned: &mut bool, on_state: &mut impl FnMut(State) -> bool) -> bool {
+    let Some(st) = daemon.parse_state(line) else {
+        return false;
+    };
+    if let Some(v) = st.schema_version.filter(|v| !*warned && *v > SCHEMA_VERSION) {
+        *warned = true;
+        eprintln!("mpris-bridgec: events use schema {v} (newer than {SCHEMA_VERSION}); update mpris-bridgec");
+    }
+    on_state(st)
+}
+
+// Reconnects if the daemon restarts. Each connection starts with the current state; the first
+// one is skipped, since callers have already read it from the snapshot (like a file tail).
+fn tail_subscription(daemon: &Daemon, expired: &impl Fn() -> bool, mut on_state: impl FnMut(State) -> bool) -> bool {
+    let mut first = true;
+    while !expired() {
+        let Ok(mut stream) = UnixStream::connect(daemon.socket_path()) else {
+            thread::sleep(Duration::from_millis(300));
+            continue;
+        };
+        // Short reads so the deadline is checked while nothing happens
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(250)));
+        if writeln!(stream, "{}", json!({"cmd":"subscribe"})).is_err() {
+            continue;
+        }
+        let mut reader = BufReader::new(stream);
+        if std::mem::take(&mut first) {
+            let _ = reader.read_line(&mut String::new());
+        }
+        if read_states(daemon, &mut reader, expired, &mut on_state) {
+            return true;
+        }
+    }
+    false
+}
+
+// States from a newline-framed stream until `on_state` is satisfied (true), or EOF/expiry (false).
+fn read_states(
+    daemon: &Daemon,
+    reader: &mut impl BufRead,
+    expired: &impl Fn() -> bool,
+    on_state: &mut impl FnMut(State) -> bool,
+) -> bool {
+    let mut warned = false;
+    let mut line = String::new();
+    while !expired() {
+        match reader.read_line(&mut line) {
+            Ok(0) => return false,
+            Ok(_) => {
+                if handle_event_line(daemon, &line, &mut warned, on_state) {
+                    return true;
+                }
+                line.clear();
+            }
+            // Timeout: a partial line stays in `line` and is completed by the next read
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_accepts_seconds_and_clock_formats() {
+        assert_eq!(parse_time("90"), Some(90.0));
+        assert_eq!(parse_time("1:30"), Some(90.0));
+        assert_eq!(parse_time("1:05:00"), Some(3900.0));
+        assert_eq!(parse_offset("+1:30"), Some(90.0));
+        assert_eq!(parse_offset("-10"), Some(-10.0));
+        for garbage in ["", "abc", "1:", ":30", "1:75", "1:60:00", "1:2:3:4", "1:-5", "nan"] {
+            assert_eq!(parse_time(garbage), None, "{garbage:?}");
+        }
+    }
+
+    #[test]
+    fn field_value_treats_null_and_empty_as_missing() {
+        let st = json!({"title":"Song","artist":"","position":12.5,"canNext":1,"bus":null});
+        assert_eq!(field_value(&st, "title").as_deref(), Some("Song"));
+        assert_eq!(field_value(&st, "position").as_deref(), Some("12.5"));
+        assert_eq!(field_value(&st, "canNext").as_deref(), Some("1"));
+        assert_eq!(field_value(&st, "artist"), None);
+        assert_eq!(field_value(&st, "bus"), None);
+        assert_eq!(field_value(&st, "nope"), None);
+    }
+
+    #[test]
+    fn polybar_actions_wrap_label_and_escape() {
+        let clicks = PolybarClicks {
+            left: "mpris-bridgec play-pause --player {player}".into(),
+            middle: "notify-send a:b".into(),
+            right: "mpris-bridgec next".into(),
+        };
+        assert_eq!(
+            polybar_actions("100% Song", "spotify", &clicks),
+            "%{A1:mpris-bridgec play-pause --player spotify:}%{A2:notify-send a\\:b:}%{A3:mpris-bridgec next:}100%% Song%{A}%{A}%{A}"
+        );
+        assert_eq!(polybar_actions("", "spotify", &clicks), "");
+        assert_eq!(polybar_actions("idle", "", &clicks), "idle");
+    }
+
+    #[test]
+    fn progress_tokens_render_bar_and_percent() {
+        let chars = ('#', '-');
+        assert_eq!(progress_tokens("[{bar:10}] {percent}%", 30.0, 120.0, chars), "[###-------] 25%");
+        assert_eq!(progress_tokens("{bar:4}", 500.0, 120.0, chars), "####");
+        // Unknown length: empty bar, no percent
+        assert_eq!(progress_tokens("{bar:5}|{percent}", 30.0, 0.0, chars), "-----|");
+        assert_eq!(progress_tokens("{title} {bar:x}", 1.0, 2.0, chars), "{title} {bar:x}");
+        assert_eq!(progress_tokens("{bar:x} {bar:4}", 1.0, 2.0, chars), "{bar:x} ##--");
+    }
+
+    #[test]
+    fn disabled_events_follow_the_subscription_stream() {
+        assert!(config_disables_events(&json!({"config": {"output": {"events_path": ""}}})));
+        assert!(!config_disables_events(&json!({"config": {"output": {"events_path": "/run/x/events.jsonl"}}})));
+
+        let stream = "{\"name\":\"mpv\",\"status\":\"playing\"}\nnot json\n{\"name\":\"mpv\",\"status\":\"paused\"}\n";
+        let mut seen = vec![];
+        let mut on_state = |st: State| {
+            seen.push(st.status.unwrap_or_default());
+            seen.last().is_some_and(|s| s == "paused")
+        };
+        let daemon = Daemon::new(None, false);
+        // No daemon to ask for output.field_names: the keys are taken as they are
+        daemon.config.set(serde_json::Value::Null).unwrap();
+        assert!(read_states(&daemon, &mut stream.as_bytes(), &|| false, &mut on_state));
+        assert_eq!(seen, ["playing", "paused"]);
+        // EOF (daemon gone) without a match
+        assert!(!read_states(&daemon, &mut "{\"status\":\"playing\"}\n".as_bytes(), &|| false, &mut |_| false));
+    }
+
+    #[test]
+    fn renamed_fields_are_read_under_their_standard_names() {
+        let daemon = Daemon::new(None, false);
+        let config = json!({"config": {"output": {"field_names": {"title": "track", "name": "player"}}}});
+        daemon.config.set(config).unwrap();
+        let st = daemon.parse_state("{\"player\":\"mpv\",\"track\":\"Song\",\"status\":\"playing\"}").unwrap();
+        assert_eq!(st.name.as_deref(), Some("mpv"));
+        assert_eq!(st.title.as_deref(), Some("Song"));
+        let v = daemon.state_value("{\"track\":\"Song\"}", &["title"]).unwrap();
+        assert_eq!(field_value(&v, "title").as_deref(), Some("Song"));
+    }
+
+    #[test]
+    fn last_events_spans_rotated_file() {
+        let dir = env::temp_dir().join(format!("mpris-bridgec-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        fs::write(dir.join("events.jsonl.1"), "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n").unwrap();
+        fs::write(&path, "{\"n\":4}\n").unwrap();
+        assert_eq!(last_events(&path, 2), ["{\"n\":3}", "{\"n\":4}"]);
+        assert_eq!(last_events(&path, 1), ["{\"n\":4}"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
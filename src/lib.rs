@@ -0,0 +1,146 @@
+//! Shared helpers for `mpris-bridged` (daemon) and `mpris-bridgec` (client).
+//! Both binaries must agree on runtime paths, argument spellings and escaping, so they live here.
+
+#![deny(unsafe_code)]
+#![deny(clippy::all, clippy::pedantic, clippy::nursery, clippy::perf)]
+#![allow(clippy::module_name_repetitions, clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+use std::path::PathBuf;
+
+/// Runtime base directory: `$MPRIS_BRIDGE_RUNTIME_DIR`, else `$XDG_RUNTIME_DIR`,
+/// else `/run/user/<uid>` for the current user.
+#[must_use]
+pub fn runtime_dir() -> String {
+    std::env::var("MPRIS_BRIDGE_RUNTIME_DIR")
+        .or_else(|_| std::env::var("XDG_RUNTIME_DIR"))
+        .unwrap_or_else(|_| {
+            let uid = nix::unistd::Uid::current().as_raw();
+            format!("/run/user/{uid}")
+        })
+}
+
+/// Version of the snapshot/event JSON (`schemaVersion`); bump when fields change.
+pub const SCHEMA_VERSION: u32 = 7;
+
+/// MPRIS `LoopStatus` for a CLI/IPC loop mode (`none` | `track` | `playlist`).
+#[must_use]
+pub fn loop_status(mode: &str) -> Option<&'static str> {
+    match mode {
+        "none" => Some("None"),
+        "track" => Some("Track"),
+        "playlist" => Some("Playlist"),
+        _ => None,
+    }
+}
+
+/// Instance name from `--instance <name>` (removed from `args`), else `$MPRIS_BRIDGE_INSTANCE`;
+/// None for the default instance. Names become a directory suffix, so `/` is rejected.
+pub fn take_instance_flag(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let mut name = std::env::var("MPRIS_BRIDGE_INSTANCE").ok();
+    if let Some(i) = args.iter().position(|a| a == "--instance") {
+        if i + 1 >= args.len() {
+            return Err("--instance needs a name".into());
+        }
+        name = Some(args.remove(i + 1));
+        args.remove(i);
+    }
+    match name.filter(|n| !n.is_empty()) {
+        Some(n) if n.contains(['/', '\0']) => Err(format!("invalid instance name {n:?}: must not contain '/'")),
+        name => Ok(name),
+    }
+}
+
+/// Per-instance runtime directory: `<runtime_dir>/mpris-bridge`, or `<runtime_dir>/mpris-bridge-<instance>`.
+#[must_use]
+pub fn instance_dir(instance: Option<&str>) -> PathBuf {
+    let sub = instance.map_or_else(|| "mpris-bridge".to_string(), |name| format!("mpris-bridge-{name}"));
+    PathBuf::from(runtime_dir()).join(sub)
+}
+
+/// IPC socket: `<instance_dir>/mpris-bridge.sock`.
+#[must_use]
+pub fn socket_path(instance: Option<&str>) -> PathBuf {
+    instance_dir(instance).join("mpris-bridge.sock")
+}
+
+/// Escape Pango markup. `&` must go first, or the other entities get double-escaped.
+#[must_use]
+pub fn pango_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+/// Cut `s` to `max` chars including the trailing `…`. With `word_boundary`, cut at the last
+/// whitespace before the limit unless that would leave less than half of `max` (a single very long word).
+#[must_use]
+pub fn truncate(s: &str, max: usize, word_boundary: bool) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let keep = max.saturating_sub(1);
+    let head = s.chars().take(keep).collect::<String>();
+    if word_boundary {
+        let next_is_space = s.chars().nth(keep).is_some_and(char::is_whitespace);
+        let cut = if next_is_space { Some(head.len()) } else { head.rfind(char::is_whitespace) };
+        if let Some(i) = cut {
+            let words = head[..i].trim_end();
+            if words.chars().count() >= max / 2 {
+                return format!("{words}…");
+            }
+        }
+    }
+    head + "…"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pango_escape_all_specials() {
+        assert_eq!(pango_escape(r#"<a href='x'>"R&B"</a>"#), "&lt;a href=&apos;x&apos;&gt;&quot;R&amp;B&quot;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn pango_escape_does_not_double_escape() {
+        assert_eq!(pango_escape("&amp;"), "&amp;amp;");
+        assert_eq!(pango_escape("&lt;"), "&amp;lt;");
+        assert_eq!(pango_escape("<"), "&lt;");
+    }
+
+    #[test]
+    fn instance_flag_is_taken_and_validated() {
+        let mut args = vec!["mpris-bridgec".to_string(), "--instance".into(), "work".into(), "status".into()];
+        assert_eq!(take_instance_flag(&mut args), Ok(Some("work".into())));
+        assert_eq!(args, ["mpris-bridgec", "status"]);
+        let mut args = vec!["--instance".to_string(), "../x".into()];
+        assert!(take_instance_flag(&mut args).is_err());
+        assert_eq!(instance_dir(Some("work")).file_name().unwrap(), "mpris-bridge-work");
+    }
+
+    #[test]
+    fn pango_escape_keeps_unicode() {
+        assert_eq!(pango_escape("Сплин — «Романс» & 東京"), "Сплин — «Романс» &amp; 東京");
+    }
+
+    #[test]
+    fn truncate_on_word_boundary() {
+        let s = "The Dark Side of the Moon";
+        assert_eq!(truncate(s, 12, false), "The Dark Si…");
+        assert_eq!(truncate(s, 12, true), "The Dark…");
+        // Cut point right before a space keeps the whole last word.
+        assert_eq!(truncate(s, 9, true), "The Dark…");
+        assert_eq!(truncate(s, 40, true), s);
+    }
+
+    #[test]
+    fn truncate_single_long_word_falls_back_to_char_cut() {
+        let s = "Supercalifragilisticexpialidocious";
+        assert_eq!(truncate(s, 10, true), "Supercali…");
+        // A space too early (below half the limit) is not used.
+        assert_eq!(truncate("A Supercalifragilistic", 10, true), "A Superca…");
+    }
+}
@@ -5,9 +5,9 @@
 //! - Lightweight IPC over Unix socket for media controls (play-pause/next/previous/seek).
 //!
 //! Notes:
-//! - We use MessageStream to receive signals and cheap "seed" via playerctl when needed.
+//! - We use `MessageStream` to receive signals and cheap "seed" via playerctl when needed.
 //! - No unsafe. Avoid holding locks across awaits. Futures are Send.
-//! - For IPC we use blocking std::os::unix sockets on a dedicated blocking task; no extra tokio features needed.
+//! - For IPC we use blocking `std::os::unix` sockets on a dedicated blocking task; no extra tokio features needed.
 
 #![deny(unsafe_code)]
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, clippy::perf)]
@@ -25,32 +25,39 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use std::{
-    collections::{HashMap, HashSet},
-    fs::{self, OpenOptions},
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, File, OpenOptions},
     io::Write,
+    net::{TcpListener, TcpStream},
     os::unix::fs::PermissionsExt,
     os::unix::net::{UnixListener, UnixStream},
     path::{Path, PathBuf},
     process::Stdio,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc, RwLock,
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
     process::{Child, Command},
     signal::unix::{signal, SignalKind},
-    sync::watch,
+    sync::{broadcast, mpsc, watch, Notify, Semaphore, SemaphorePermit},
     task,
     time::Instant, // <-- добавлено: используем для дебаунса
 };
-use zbus::{fdo::DBusProxy, Connection, MessageStream, MessageType};
+use tracing::{debug, error, info, warn};
+use zbus::{
+    fdo::DBusProxy,
+    names::BusName,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+    Connection, MatchRule, MessageStream, MessageType,
+};
 
 // ------------------------- Config -------------------------
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 struct Config {
     #[serde(default)]
     selection: Selection,
@@ -60,12 +67,16 @@ struct Config {
     output: Output,
     #[serde(default)]
     presentation: Presentation,
-    #[allow(dead_code)]
     #[serde(default)]
     logging: Logging,
+    #[serde(default)]
+    idle: Idle,
+    #[serde(skip)]
+    instance: Option<String>, // --instance / $MPRIS_BRIDGE_INSTANCE, not a config key
 }
 
-#[derive(Debug, Deserialize)]
+#[allow(clippy::struct_excessive_bools)] // config keys are user-facing
+#[derive(Debug, Deserialize, Serialize)]
 struct Selection {
     #[serde(default = "default_priority")]
     priority: Vec<String>,
@@ -77,11 +88,60 @@ struct Selection {
     include: Vec<String>,
     #[serde(default)]
     exclude: Vec<String>,
+    #[serde(default)]
+    sticky: bool, // keep a playing selection until it stops, even if a higher-priority player plays
+    #[serde(default = "default_active_statuses")]
+    active_statuses: Vec<String>, // statuses that count as "playing" for selection
+    #[serde(default = "d150")]
+    focus_debounce_ms: u64, // coalesce bursts of focus changes (fast alt-tab)
+    #[serde(default = "d4usize")]
+    max_concurrent_queries: usize, // playerctl/busctl query processes running at once
+    #[serde(default)]
+    per_monitor: bool, // Hyprland only: follow the focused monitor's most recent window
+    #[serde(default = "d10")]
+    seek_step_secs: u64, // step of the seek-forward/seek-backward IPC commands
+    #[serde(default)]
+    control_blacklist: Vec<String>, // name prefixes shown/selected as usual but never controlled via IPC
+    #[serde(default = "default_focus_source")]
+    focus_source: String, // "hyprland" | "playback" (most recently started player; no compositor needed)
+    #[serde(default = "d500")]
+    player_exit_grace_ms: u64, // keep a vanished player this long in case it reappears (track skips)
+    #[serde(default)]
+    require_audio: bool, // prefer playing players with an audible PulseAudio/PipeWire stream (pactl)
+    #[serde(default = "d15000")]
+    follower_stall_ms: u64, // respawn a follower silent this long while its player plays (0 = off)
+    #[serde(default)]
+    player_args: HashMap<String, Vec<String>>, // prefix -> extra playerctl arguments (verbatim, not shell-parsed)
+    #[serde(default = "dtrue")]
+    keep_on_unrelated_focus: bool, // focusing a window without a player (terminal) keeps the last focus hint
+    #[serde(default = "default_policy")]
+    policy: String, // "ordered" (first match down the focus/pin/priority chain) | "weighted" (see weighted_choice)
+    #[serde(default = "dtrue")]
+    resync_on_resume: bool, // reseed players after resume from suspend (logind PrepareForSleep, system bus)
+    #[serde(default)]
+    max_players: Option<usize>, // track at most this many players (guards against apps spawning hundreds)
+    #[serde(default)]
+    weights: HashMap<String, i64>, // prefix -> weight for policy = "weighted" (unlisted players: 1)
+}
+fn default_policy() -> String {
+    "ordered".into()
+}
+const fn d15000() -> u64 {
+    15000
+}
+fn default_focus_source() -> String {
+    "hyprland".into()
 }
 fn default_priority() -> Vec<String> {
     vec!["firefox".into(), "spotify".into(), "vlc".into(), "mpv".into()]
 }
-fn dtrue() -> bool {
+fn default_active_statuses() -> Vec<String> {
+    vec!["Playing".into()]
+}
+const fn d4usize() -> usize {
+    4
+}
+const fn dtrue() -> bool {
     true
 }
 fn fallback_any() -> String {
@@ -95,28 +155,90 @@ impl Default for Selection {
             fallback: "any".into(),
             include: vec![],
             exclude: vec![],
+            sticky: false,
+            active_statuses: default_active_statuses(),
+            focus_debounce_ms: d150(),
+            max_concurrent_queries: d4usize(),
+            per_monitor: false,
+            seek_step_secs: d10(),
+            control_blacklist: vec![],
+            focus_source: default_focus_source(),
+            player_exit_grace_ms: d500(),
+            require_audio: false,
+            follower_stall_ms: d15000(),
+            player_args: HashMap::new(),
+            keep_on_unrelated_focus: true,
+            resync_on_resume: true,
+            max_players: None,
+            policy: default_policy(),
+            weights: HashMap::new(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[allow(clippy::struct_excessive_bools)] // config keys are user-facing
+#[derive(Debug, Deserialize, Serialize)]
 struct Art {
     #[serde(default = "dtrue")]
     enabled: bool,
     #[serde(default = "dtrue")]
     download_http: bool,
     #[serde(default = "d5000")]
-    timeout_ms: u64,
+    timeout_ms: u64, // per download attempt
+    #[serde(default)]
+    download_retries: u32, // extra attempts after a failed download
+    #[serde(default)]
+    total_timeout_ms: Option<u64>, // budget for all attempts together; default: none beyond timeout_ms each
     #[serde(default)]
     cache_dir: Option<String>,
     #[serde(default)]
-    default_image: Option<String>,
+    http_cache_dir: Option<String>, // downloaded http(s) art; default: cache_dir
+    #[serde(default)]
+    embedded_cache_dir: Option<String>, // when set, file:// art is copied here (keyed by path + mtime)
+    #[serde(default)]
+    default_image: Option<String>, // no artUrl, or a missing file
+    #[serde(default)]
+    error_image: Option<String>, // http(s) download failed (timeout, non-2xx); default: default_image
     #[serde(default)]
     current_path: Option<String>,
+    #[serde(default = "default_addressing")]
+    current_mode_addressing: String, // "fixed": copy to current_path | "content": thumbnail is the cache file itself
     #[serde(default)]
     use_symlink: bool,
+    #[serde(default)]
+    per_player_current_dir: Option<String>, // also write {dir}/{player}.jpg for each followed player
+    #[serde(default)]
+    blank_thumbnail: Option<String>, // thumbnail of blank states; "" = none; default: default_image
+    #[serde(default = "d3u32")]
+    fail_threshold: u32, // failed downloads of one URL before it is skipped (0 = never skip)
+    #[serde(default = "d600")]
+    fail_ttl_secs: u64, // how long a failing URL is skipped before retrying
+    #[serde(default)]
+    corner_radius: Option<u32>, // round the cover's corners (px); the result is a PNG with alpha
+    #[serde(default)]
+    async_pipeline: bool, // write text first; a worker resolves the cover and writes a follow-up snapshot
+    #[serde(default)]
+    loading_image: Option<String>, // async_pipeline: thumbnail until the new cover is ready (default: keep the previous one)
+}
+fn default_addressing() -> String {
+    "fixed".into()
+}
+const fn d3u32() -> u32 {
+    3
+}
+const fn d600() -> u64 {
+    600
+}
+const fn d10() -> u64 {
+    10
+}
+const fn d150() -> u64 {
+    150
+}
+const fn d500() -> u64 {
+    500
 }
-fn d5000() -> u64 {
+const fn d5000() -> u64 {
     5000
 }
 impl Default for Art {
@@ -125,41 +247,120 @@ impl Default for Art {
             enabled: true,
             download_http: true,
             timeout_ms: d5000(),
+            download_retries: 0,
+            total_timeout_ms: None,
             cache_dir: None,
+            http_cache_dir: None,
+            embedded_cache_dir: None,
             default_image: None,
+            error_image: None,
             current_path: None,
+            current_mode_addressing: default_addressing(),
             use_symlink: false,
+            per_player_current_dir: None,
+            blank_thumbnail: None,
+            fail_threshold: d3u32(),
+            fail_ttl_secs: d600(),
+            corner_radius: None,
+            async_pipeline: false,
+            loading_image: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[allow(clippy::struct_excessive_bools)] // config keys are user-facing
+#[derive(Debug, Deserialize, Serialize)]
 struct Output {
     #[serde(default)]
     snapshot_path: Option<String>,
     #[serde(default)]
-    events_path: Option<String>,
+    events_path: Option<String>, // "" = no events file
+    #[serde(default)]
+    events_memory_ring: Option<usize>, // keep the last N states in memory for IPC "recent-events"
     #[serde(default)]
     pretty_snapshot: bool,
+    #[serde(default)]
+    defer_first_write: bool, // skip blank snapshots until real metadata (or a timeout)
+    #[serde(default)]
+    fsync: bool, // sync the temp snapshot before renaming it into place
+    #[serde(default = "dtrue")]
+    events_include_position_only: bool, // false: "position" events (seek, poll_position_ms) skip events.jsonl
+    #[serde(default)]
+    events_timestamp: bool, // prepend "ts" (RFC3339 UTC) to each events.jsonl line
+    #[serde(default)]
+    poll_position_ms: Option<u64>, // read the real position of the selected playing player this often
+    #[serde(default)]
+    notify_on_track_change: bool, // desktop notification (notify-send) when the selected player's track changes
+    #[serde(default = "d5000")]
+    notify_timeout_ms: u64,
+    #[serde(default = "dtrue")]
+    quick_snapshot: bool, // one-shot metadata query on selection change and other re-emits (false: wait for the follower)
+    #[serde(default)]
+    ipc_tcp_listen: Option<String>, // e.g. "127.0.0.1:9124": IPC over TCP as well (no auth!)
+    #[serde(default = "default_time_unit")]
+    time_unit: String, // "seconds" | "milliseconds" (integer) for position/length
+    #[serde(default)]
+    field_names: HashMap<String, String>, // camelCase key -> key written instead (e.g. title = "track")
+    #[serde(default)]
+    extra: Vec<ExtraOutput>,
+}
+fn default_time_unit() -> String {
+    "seconds".into()
 }
 impl Default for Output {
     fn default() -> Self {
         Self {
             snapshot_path: None,
             events_path: None,
+            events_memory_ring: None,
             pretty_snapshot: false,
+            defer_first_write: false,
+            fsync: false,
+            events_include_position_only: true,
+            events_timestamp: false,
+            poll_position_ms: None,
+            notify_on_track_change: false,
+            notify_timeout_ms: d5000(),
+            quick_snapshot: true,
+            ipc_tcp_listen: None,
+            time_unit: default_time_unit(),
+            field_names: HashMap::new(),
+            extra: vec![],
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+// `[[output.extra]]`: a fixed player followed into its own files, independent of selection.
+#[derive(Debug, Deserialize, Serialize)]
+struct ExtraOutput {
+    player: String, // name prefix
+    snapshot_path: String,
+    events_path: String,
+}
+
+#[allow(clippy::struct_field_names)] // config keys are user-facing
+#[derive(Debug, Deserialize, Serialize)]
 struct Presentation {
     #[serde(default = "d120usize")]
     truncate_title: usize,
     #[serde(default = "d120usize")]
     truncate_artist: usize,
+    #[serde(default)]
+    truncate_word_boundary: bool,
+    #[serde(default)]
+    status_icons: StatusIcons,
+    // Player-name prefix -> icons, consulted before `status_icons`
+    #[serde(default)]
+    player_status_icons: HashMap<String, StatusIcons>,
+    #[serde(default = "default_progress_format")]
+    progress_format: String, // `progressStr`; tokens {position}, {length}
+    #[serde(default = "dtrue")]
+    artist_fallback_album_artist: bool, // empty xesam:artist -> xesam:albumArtist
+}
+fn default_progress_format() -> String {
+    "{position} / {length}".into()
 }
-fn d120usize() -> usize {
+const fn d120usize() -> usize {
     120
 }
 impl Default for Presentation {
@@ -167,23 +368,74 @@ impl Default for Presentation {
         Self {
             truncate_title: d120usize(),
             truncate_artist: d120usize(),
+            truncate_word_boundary: false,
+            status_icons: StatusIcons::default(),
+            player_status_icons: HashMap::new(),
+            progress_format: default_progress_format(),
+            artist_fallback_album_artist: dtrue(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct StatusIcons {
+    playing: Option<String>,
+    paused: Option<String>,
+    stopped: Option<String>,
+}
+impl StatusIcons {
+    // `status`: normalized (see normalize_status), so custom active statuses get the playing icon.
+    fn get(&self, status: &str) -> Option<&str> {
+        match status {
+            "playing" => self.playing.as_deref(),
+            "paused" => self.paused.as_deref(),
+            "stopped" => self.stopped.as_deref(),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct Logging {
-    #[allow(dead_code)]
     #[serde(default = "default_level")]
-    level: String,
+    level: String, // "error" | "warn" | "info" | "debug" | "trace"
+    #[serde(default = "default_log_format")]
+    format: String, // "text" | "json"
+    #[serde(default)]
+    trace_dbus: bool, // log every matched D-Bus signal at debug level (--trace-dbus)
 }
 fn default_level() -> String {
-    "warn".into()
+    "info".into()
+}
+fn default_log_format() -> String {
+    "text".into()
 }
 impl Default for Logging {
     fn default() -> Self {
         Self {
             level: default_level(),
+            format: default_log_format(),
+            trace_dbus: false,
+        }
+    }
+}
+
+// Back off the follower watchdogs once nothing has played for a while (fewer wakeups on battery).
+#[derive(Debug, Deserialize, Serialize)]
+struct Idle {
+    #[serde(default = "d60")]
+    after_secs: u64, // 0 disables the backoff
+    #[serde(default = "d10")]
+    watchdog_secs: u64, // watchdog interval while idle (2s otherwise)
+}
+const fn d60() -> u64 {
+    60
+}
+impl Default for Idle {
+    fn default() -> Self {
+        Self {
+            after_secs: d60(),
+            watchdog_secs: d10(),
         }
     }
 }
@@ -193,101 +445,283 @@ impl Default for Logging {
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct UiState {
+    schema_version: u32, // mpris_bridge::SCHEMA_VERSION
+    event: &'static str, // "update" | "position" (optimistic seek, position poll) | "reconnect" (D-Bus gap)
     name: String,
+    bus_name: String, // org.mpris.MediaPlayer2.<name>, for direct D-Bus calls
     title: String,
     artist: String,
-    status: String,
+    track_hash: String, // see track_hash()
+    status: String,     // normalized: "playing" | "paused" | "stopped" | "" (no player)
+    status_raw: String, // as reported by the player
+    status_icon: String,
     position: f64,
     position_str: String,
+    position_ts: f64, // Unix epoch seconds when `position` was sampled
     length: f64,
     length_str: String,
+    progress_str: String, // presentation.progress_format; just the position for unknown length (streams)
     thumbnail: String,
+    art_width: i32, // pixel size of the cover; 0 when there is no art (default cover) or it can't be read
+    art_height: i32,
     can_next: i32,
     can_prev: i32,
+    can_control: i32, // 0: the player ignores control (MPRIS CanControl=false)
+    fullscreen: bool, // MPRIS root Fullscreen (false when unsupported)
+    source_kind: String, // see source_kind()
+    selection_reason: String, // "focus" | "pinned" | "priority" | "sticky" | "playing-only" | "remember_last" | "fallback-any" | "weighted" | "locked"
+    any_playing: bool, // some eligible player (not only the selected one) is playing
 }
 impl UiState {
     fn empty(default_cover: &str) -> Self {
         Self {
+            schema_version: mpris_bridge::SCHEMA_VERSION,
+            event: "update",
             name: String::new(),
+            bus_name: String::new(),
             title: String::new(),
             artist: String::new(),
+            track_hash: String::new(),
             status: String::new(),
+            status_raw: String::new(),
+            status_icon: String::new(),
             position: 0.0,
             position_str: fmt_time(0.0),
+            position_ts: epoch_secs(),
             length: 0.0,
             length_str: fmt_time(0.0),
+            progress_str: String::new(),
             thumbnail: default_cover.to_string(),
+            art_width: 0,
+            art_height: 0,
             can_next: 0,
             can_prev: 0,
+            can_control: 1,
+            fullscreen: false,
+            source_kind: String::new(),
+            selection_reason: String::new(),
+            any_playing: false,
         }
     }
 }
 
+// While frozen, the newest state not written yet, with its write sequence number.
+#[derive(Debug, Default)]
+enum Freeze {
+    #[default]
+    Off,
+    On(Option<(u64, Box<UiState>)>),
+}
+
+type MeasuredCover = ((PathBuf, Option<SystemTime>), (i32, i32)); // ((file, mtime), (width, height))
+
+// Output files of one `[[output.extra]]` entry and the liveness of its follower.
+#[derive(Debug)]
+struct ExtraSink {
+    player: String,
+    snapshot_path: PathBuf,
+    events_path: PathBuf,
+    alive: AtomicBool,
+    generation: AtomicU64, // bumped per respawn, like Ctx::follower_gen
+    held: std::sync::Mutex<Option<UiState>>, // newest state not written while frozen
+}
+
 #[derive(Debug)]
 struct Ctx {
     cfg: Config,
-    cache_dir: PathBuf,
+    http_cache_dir: PathBuf,
+    embedded_cache_dir: Option<PathBuf>,
     default_cover: PathBuf,
+    error_cover: PathBuf, // art.error_image, else default_cover
+    loading_cover: Option<PathBuf>, // art.loading_image
     current_cover: PathBuf,
+    per_player_art_dir: Option<PathBuf>,
+    blank_thumbnail: String, // thumbnail for blank states (may be empty)
     snapshot_path: PathBuf,
     events_path: PathBuf,
+    socket_path: PathBuf,
 
     // Known players and their statuses
-    players: RwLock<HashSet<String>>,        // simple names like "firefox.instance_1_240"
+    players: RwLock<HashSet<String>>, // simple names like "firefox.instance_1_240"
+    players_dropped: AtomicUsize, // players left out by selection.max_players at the last seed
+    last_seen: RwLock<HashMap<String, Instant>>, // last listing that contained each player
     status: RwLock<HashMap<String, String>>, // "Playing"/"Paused"/"Stopped"
 
     // Selection & focus
     selected: RwLock<Option<String>>,
+    selection_reason: RwLock<&'static str>, // why `selected` won, see recompute_selected
     last_selected: RwLock<Option<String>>,
     focus_hint: RwLock<Option<String>>, // "firefox"/"spotify"/...
+    playing_since: RwLock<HashMap<String, Instant>>, // when each playing player started; recency tiebreaker
+    audible_apps: RwLock<Option<Vec<String>>>, // require_audio; None = unknown (no pactl)
+    pinned: RwLock<Option<(String, Instant)>>, // (prefix, expiry) from IPC "pin"
+    selection_lock: RwLock<Option<(String, Instant)>>, // (player, expiry) from IPC "toggle-players"
+
+    // (title, artist) truncation limits; start from [presentation], adjustable via IPC
+    truncation: RwLock<(usize, usize)>,
 
-    // Follower process flag
+    // Follower process flag and the time of its last line (stall detection)
     follower_alive: AtomicBool,
+    follower_heartbeat: RwLock<Instant>,
+    // Main follower generation (an old reader's exit must not clear the new one's flag)
+    // and the number of unreaped main follower children
+    follower_gen: AtomicU64,
+    followers_live: AtomicUsize,
+
+    // Bumped per track change; a pending notification only fires if still current
+    notify_seq: AtomicU64,
+
+    // [[output.extra]] followers
+    extra_outputs: Vec<Arc<ExtraSink>>,
+
+    // False while `output.defer_first_write` holds back blank snapshots
+    first_write_done: AtomicBool,
+
+    // output.events_memory_ring: newest last
+    events_ring: std::sync::Mutex<VecDeque<UiState>>,
+
+    // write_state ordering: sequence handed out per call, and the last one written (held while writing)
+    write_seq: AtomicU64,
+    write_lock: std::sync::Mutex<u64>,
+
+    // Last written state (base for optimistic updates)
+    last_state: RwLock<Option<UiState>>,
+
+    // IPC "freeze"
+    frozen: RwLock<Freeze>,
+
+    // The last measured cover
+    art_dims: RwLock<Option<MeasuredCover>>,
+
+    // artUrl -> (recent download failures, last failure); see art.fail_threshold
+    art_failures: RwLock<HashMap<String, (u32, Instant)>>,
+
+    // art.async_pipeline: cover of the main output's current track, and the worker queue
+    art_current: RwLock<Option<ArtJob>>,
+    art_tx: mpsc::Sender<ArtJob>,
+    art_rx: std::sync::Mutex<Option<mpsc::Receiver<ArtJob>>>, // taken by art_worker
+
+    // Art downloads in progress (awaited on shutdown)
+    art_inflight: AtomicUsize,
+
+    // Caps concurrent playerctl/busctl query subprocesses
+    query_slots: Semaphore,
+
+    // Last time any player was playing; `idle_wake` fires when playback resumes after idling
+    last_playing: RwLock<Instant>,
+    idle_wake: Notify,
+
+    // Every written state as a JSON line, for IPC subscribers
+    events_tx: broadcast::Sender<String>,
 
     // Notify follower manager on selection changes
     sel_tx: watch::Sender<Option<String>>,
 }
 impl Ctx {
     fn new(cfg: Config, sel_tx: watch::Sender<Option<String>>) -> Self {
-        let cache_dir =
-            PathBuf::from(expand(cfg.art.cache_dir.as_deref().unwrap_or("$XDG_CACHE_HOME/mpris-bridge/art")));
+        let cache_dir = cfg.art.cache_dir.as_deref().unwrap_or("$XDG_CACHE_HOME/mpris-bridge/art");
+        let http_cache_dir = PathBuf::from(expand(cfg.art.http_cache_dir.as_deref().unwrap_or(cache_dir)));
+        let embedded_cache_dir = cfg.art.embedded_cache_dir.as_deref().map(|d| PathBuf::from(expand(d)));
         let default_cover = PathBuf::from(expand(
             cfg.art
                 .default_image
                 .as_deref()
                 .unwrap_or("$HOME/.config/eww/scripts/cover.png"),
         ));
+        let error_cover = cfg.art.error_image.as_deref().map_or_else(|| default_cover.clone(), |p| PathBuf::from(expand(p)));
+        let loading_cover = cfg.art.loading_image.as_deref().map(|p| PathBuf::from(expand(p)));
         let current_cover = PathBuf::from(expand(
             cfg.art
                 .current_path
                 .as_deref()
                 .unwrap_or("$HOME/.config/eww/image.jpg"),
         ));
-        let snapshot_path = PathBuf::from(expand(
-            cfg.output
-                .snapshot_path
-                .as_deref()
-                .unwrap_or("$XDG_RUNTIME_DIR/mpris-bridge/state.json"),
-        ));
-        let events_path = PathBuf::from(expand(
-            cfg.output
-                .events_path
-                .as_deref()
-                .unwrap_or("$XDG_RUNTIME_DIR/mpris-bridge/events.jsonl"),
-        ));
+        let blank_thumbnail = cfg
+            .art
+            .blank_thumbnail
+            .as_deref()
+            .map_or_else(|| default_cover.to_string_lossy().to_string(), expand);
+        let per_player_art_dir = cfg.art.per_player_current_dir.as_deref().map(|d| PathBuf::from(expand(d)));
+        // Defaults live in the per-instance runtime dir (see --instance)
+        let instance_dir = mpris_bridge::instance_dir(cfg.instance.as_deref());
+        let socket_path = mpris_bridge::socket_path(cfg.instance.as_deref());
+        let snapshot_path = cfg
+            .output
+            .snapshot_path
+            .as_deref()
+            .map_or_else(|| instance_dir.join("state.json"), |p| PathBuf::from(expand(p)));
+        let events_path = cfg
+            .output
+            .events_path
+            .as_deref()
+            .map_or_else(|| instance_dir.join("events.jsonl"), |p| PathBuf::from(expand(p)));
+        let first_write_done = AtomicBool::new(!cfg.output.defer_first_write);
+        let extra_outputs = cfg
+            .output
+            .extra
+            .iter()
+            .map(|e| {
+                Arc::new(ExtraSink {
+                    player: e.player.clone(),
+                    snapshot_path: PathBuf::from(expand(&e.snapshot_path)),
+                    events_path: PathBuf::from(expand(&e.events_path)),
+                    alive: AtomicBool::new(false),
+                    generation: AtomicU64::new(0),
+                    held: std::sync::Mutex::new(None),
+                })
+            })
+            .collect();
+        let query_slots = Semaphore::new(cfg.selection.max_concurrent_queries.max(1));
+        let truncation = RwLock::new((cfg.presentation.truncate_title, cfg.presentation.truncate_artist));
+        let (art_tx, art_rx) = mpsc::channel(ART_QUEUE_LEN);
         Self {
             cfg,
-            cache_dir,
+            http_cache_dir,
+            embedded_cache_dir,
             default_cover,
+            error_cover,
+            loading_cover,
             current_cover,
+            per_player_art_dir,
+            blank_thumbnail,
             snapshot_path,
+            socket_path,
             events_path,
             players: RwLock::new(HashSet::new()),
+            players_dropped: AtomicUsize::new(0),
+            last_seen: RwLock::new(HashMap::new()),
             status: RwLock::new(HashMap::new()),
             selected: RwLock::new(None),
+            selection_reason: RwLock::new(""),
             last_selected: RwLock::new(None),
             focus_hint: RwLock::new(None),
+            playing_since: RwLock::new(HashMap::new()),
+            audible_apps: RwLock::new(None),
+            pinned: RwLock::new(None),
+            selection_lock: RwLock::new(None),
+            truncation,
             follower_alive: AtomicBool::new(false),
+            follower_heartbeat: RwLock::new(Instant::now()),
+            follower_gen: AtomicU64::new(0),
+            followers_live: AtomicUsize::new(0),
+            notify_seq: AtomicU64::new(0),
+            extra_outputs,
+            first_write_done,
+            events_ring: std::sync::Mutex::new(VecDeque::new()),
+            write_seq: AtomicU64::new(0),
+            write_lock: std::sync::Mutex::new(0),
+            last_state: RwLock::new(None),
+            frozen: RwLock::new(Freeze::Off),
+            art_dims: RwLock::new(None),
+            art_failures: RwLock::new(HashMap::new()),
+            art_current: RwLock::new(None),
+            art_tx,
+            art_rx: std::sync::Mutex::new(Some(art_rx)),
+            art_inflight: AtomicUsize::new(0),
+            query_slots,
+            last_playing: RwLock::new(Instant::now()),
+            idle_wake: Notify::new(),
+            events_tx: broadcast::channel(64).0,
             sel_tx,
         }
     }
@@ -295,6 +729,7 @@ impl Ctx {
 
 // ------------------------- Utils -------------------------
 
+#[allow(clippy::cast_possible_truncation)] // media times are far below i64::MAX seconds
 fn fmt_time(s: f64) -> String {
     let secs = s.max(0.0).floor() as i64;
     let m = secs / 60;
@@ -302,6 +737,36 @@ fn fmt_time(s: f64) -> String {
     format!("{m}:{r:02}")
 }
 
+fn epoch_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+// RFC3339 UTC with millisecond precision, e.g. "2024-05-01T12:34:56.789Z".
+fn rfc3339_utc(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm, unsigned: no dates before the epoch)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
+
 fn expand(path: &str) -> String {
     let mut s = path.to_string();
     if let Some(home) = dirs::home_dir() {
@@ -313,12 +778,7 @@ fn expand(path: &str) -> String {
     if let Some(cache) = dirs::cache_dir() {
         s = s.replace("$XDG_CACHE_HOME", cache.to_string_lossy().as_ref());
     }
-    if let Ok(run) = std::env::var("XDG_RUNTIME_DIR") {
-        s = s.replace("$XDG_RUNTIME_DIR", &run);
-    } else {
-        let uid = nix::unistd::Uid::current().as_raw();
-        s = s.replace("$XDG_RUNTIME_DIR", &format!("/run/user/{uid}"));
-    }
+    s = s.replace("$XDG_RUNTIME_DIR", &mpris_bridge::runtime_dir());
     s
 }
 
@@ -332,7 +792,19 @@ fn ensure_dirs(ctx: &Ctx) {
     if let Some(p) = ctx.current_cover.parent() {
         let _ = fs::create_dir_all(p);
     }
-    let _ = fs::create_dir_all(&ctx.cache_dir);
+    for sink in &ctx.extra_outputs {
+        for p in [sink.snapshot_path.parent(), sink.events_path.parent()].into_iter().flatten() {
+            let _ = fs::create_dir_all(p);
+        }
+    }
+    let _ = fs::create_dir_all(&ctx.http_cache_dir);
+    if let Some(dir) = &ctx.embedded_cache_dir {
+        let _ = fs::create_dir_all(dir);
+    }
+    remove_partial_downloads(ctx);
+    if let Some(dir) = &ctx.per_player_art_dir {
+        let _ = fs::create_dir_all(dir);
+    }
 }
 
 fn include_exclude_match(name: &str, include: &[String], exclude: &[String]) -> bool {
@@ -360,226 +832,973 @@ fn map_class_to_hint(class: &str) -> Option<String> {
     }
 }
 
-fn truncate(s: &str, max: usize) -> String {
-    if s.chars().count() <= max {
-        return s.to_string();
-    }
-    s.chars().take(max.saturating_sub(1)).collect::<String>() + "…"
+// Current limits: presentation.truncate_* unless changed via IPC "set-truncation".
+fn truncate_title_artist(ctx: &Ctx, title: &str, artist: &str) -> (String, String) {
+    let (max_title, max_artist) = *ctx.truncation.read().unwrap();
+    let word_boundary = ctx.cfg.presentation.truncate_word_boundary;
+    let cut = |s: &str, max: usize| mpris_bridge::truncate(s, max, word_boundary);
+    (cut(title, max_title), cut(artist, max_artist))
 }
 
 // ------------------------- JSON I/O -------------------------
 
-async fn write_state(ctx: &Ctx, st: &UiState) -> Result<()> {
-    // snapshot (atomic)
-    let json =
-        if ctx.cfg.output.pretty_snapshot { serde_json::to_string_pretty(st)? } else { serde_json::to_string(st)? };
-    let tmp = ctx.snapshot_path.with_extension("json.tmp");
-    fs::write(&tmp, json.as_bytes())?;
-    fs::rename(&tmp, &ctx.snapshot_path)?;
-    // events (append)
-    let mut f = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&ctx.events_path)?;
-    let line = serde_json::to_string(st)?;
-    writeln!(f, "{line}")?;
-    Ok(())
-}
+// How long `output.defer_first_write` waits for real metadata before writing the blank state.
+const DEFER_FIRST_WRITE_TIMEOUT_MS: u64 = 2000;
 
-// ------------------------- Cover Art -------------------------
+// Blank/placeholder snapshot: dropped while the first real write is deferred.
+fn write_placeholder(ctx: &Ctx, st: &UiState) -> Result<()> {
+    if !ctx.first_write_done.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    write_state(ctx, st)
+}
 
-async fn update_art(ctx: &Ctx, art_url: &str) -> Result<String> {
-    if !ctx.cfg.art.enabled {
-        return Ok(ctx.current_cover.to_string_lossy().to_string());
+// Writes are serialized and numbered on entry: a state that lost the race to a newer one is
+// dropped instead of overwriting it, so the snapshot always ends up with the latest call.
+fn write_state(ctx: &Ctx, st: &UiState) -> Result<()> {
+    let seq = ctx.write_seq.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut committed = ctx.write_lock.lock().unwrap();
+    if seq < *committed {
+        debug!("dropping stale write #{seq} (#{} already written)", *committed);
+        return Ok(());
     }
-    let file_re = Regex::new(r"^file://").unwrap();
-    let http_re = Regex::new(r"^https?://").unwrap();
+    if let Freeze::On(latest) = &mut *ctx.frozen.write().unwrap() {
+        *latest = Some((seq, Box::new(st.clone())));
+        return Ok(());
+    }
+    let written = commit_state(ctx, &mut committed, seq, st);
+    drop(committed);
+    written
+}
 
-    if file_re.is_match(art_url) {
-        let local_path = art_url.trim_start_matches("file://");
-        if Path::new(local_path).is_file() {
-            ensure_current_cover(ctx, Path::new(local_path))?;
-            return Ok(ctx.current_cover.to_string_lossy().to_string());
+// Writes `st` out as write #`seq`; the caller holds the write lock (`committed`).
+fn commit_state(ctx: &Ctx, committed: &mut u64, seq: u64, st: &UiState) -> Result<()> {
+    ctx.first_write_done.store(true, Ordering::SeqCst);
+    let mut st = st.clone();
+    if !st.name.is_empty() {
+        st.selection_reason = (*ctx.selection_reason.read().unwrap()).to_string();
+    }
+    st.progress_str = progress_str(ctx, &st);
+    st.any_playing = any_playing(ctx);
+    write_output(ctx, &ctx.snapshot_path, &ctx.events_path, &st)?;
+    // No subscribers is fine
+    let _ = ctx.events_tx.send(state_json(ctx, &st, false)?);
+    if let Some(cap) = events_ring_cap(ctx) {
+        let mut ring = ctx.events_ring.lock().unwrap();
+        if ring.len() >= cap {
+            ring.pop_front();
         }
-    } else if http_re.is_match(art_url) && ctx.cfg.art.download_http {
-        let mut hasher = Sha1::new();
-        hasher.update(art_url.as_bytes());
-        let fname = format!("{:x}", hasher.finalize());
-        let target = ctx.cache_dir.join(format!("{fname}.jpg"));
-        if !target.exists() {
-            let client = reqwest::Client::builder()
-                .timeout(Duration::from_millis(ctx.cfg.art.timeout_ms))
-                .build()?;
-            let resp = client.get(art_url).send().await?;
-            if resp.status().is_success() {
-                let bytes = resp.bytes().await.unwrap_or(Bytes::new());
-                if !bytes.is_empty() {
-                    fs::write(&target, &bytes)?;
-                }
-            }
+        ring.push_back(st.clone());
+    }
+    *ctx.last_state.write().unwrap() = Some(st);
+    *committed = seq;
+    Ok(())
+}
+
+const MAX_EVENTS_RING: usize = 1000;
+
+fn events_ring_cap(ctx: &Ctx) -> Option<usize> {
+    ctx.cfg.output.events_memory_ring.filter(|n| *n > 0).map(|n| n.min(MAX_EVENTS_RING))
+}
+
+// IPC "recent-events": the last `n` ring entries, oldest first, as written out.
+fn recent_events(ctx: &Ctx, n: usize) -> Result<Vec<serde_json::Value>> {
+    let ring = ctx.events_ring.lock().unwrap();
+    ring.iter()
+        .skip(ring.len().saturating_sub(n))
+        .map(|st| Ok(serde_json::from_str(&state_json(ctx, st, false)?)?))
+        .collect()
+}
+
+// IPC "freeze": outputs stay as they are; unfreezing writes the newest state held back meanwhile.
+// Everything is flushed under the write lock and the held state keeps its own number, so a
+// write racing the unfreeze lands after it and is never overwritten by it.
+fn set_frozen(ctx: &Ctx, on: bool) -> Result<()> {
+    let mut committed = ctx.write_lock.lock().unwrap();
+    let mut frozen = ctx.frozen.write().unwrap();
+    if on {
+        if matches!(*frozen, Freeze::Off) {
+            *frozen = Freeze::On(None);
         }
-        if target.exists() {
-            ensure_current_cover(ctx, &target)?;
-            return Ok(ctx.current_cover.to_string_lossy().to_string());
+        return Ok(());
+    }
+    let Freeze::On(latest) = std::mem::take(&mut *frozen) else {
+        return Ok(());
+    };
+    for sink in &ctx.extra_outputs {
+        let held = sink.held.lock().unwrap().take();
+        if let Some(st) = held {
+            write_output(ctx, &sink.snapshot_path, &sink.events_path, &st)?;
         }
     }
-
-    ensure_current_cover(ctx, &ctx.default_cover)?;
-    Ok(ctx.current_cover.to_string_lossy().to_string())
+    drop(frozen);
+    let written = latest.map_or(Ok(()), |(seq, st)| commit_state(ctx, &mut committed, seq, &st));
+    drop(committed);
+    written
 }
 
-fn ensure_current_cover(ctx: &Ctx, src: &Path) -> Result<()> {
-    if let Some(p) = ctx.current_cover.parent() {
-        let _ = fs::create_dir_all(p);
+// UiState as written out. With output.time_unit = "milliseconds", position and length become
+// integer milliseconds (internally they stay seconds; the *Str fields are unaffected).
+// output.field_names renames keys last, so the other options keep using the camelCase names.
+fn state_json(ctx: &Ctx, st: &UiState, pretty: bool) -> Result<String> {
+    let out = &ctx.cfg.output;
+    if out.time_unit != "milliseconds" && out.field_names.is_empty() {
+        return Ok(if pretty { serde_json::to_string_pretty(st)? } else { serde_json::to_string(st)? });
     }
-    if ctx.cfg.art.use_symlink {
-        if ctx.current_cover.exists() {
-            let _ = fs::remove_file(&ctx.current_cover);
+    let mut v = serde_json::to_value(st)?;
+    if out.time_unit == "milliseconds" {
+        for key in ["position", "length"] {
+            let secs = v[key].as_f64().unwrap_or(0.0);
+            #[allow(clippy::cast_possible_truncation)] // media times are far below i64::MAX ms
+            let ms = (secs * 1000.0).round() as i64;
+            v[key] = ms.into();
         }
-        #[allow(clippy::let_underscore_must_use)]
-        let _ = std::os::unix::fs::symlink(src, &ctx.current_cover);
+    }
+    if let Some(obj) = v.as_object_mut().filter(|_| !out.field_names.is_empty()) {
+        *obj = std::mem::take(obj)
+            .into_iter()
+            .map(|(k, val)| (out.field_names.get(&k).cloned().unwrap_or(k), val))
+            .collect();
+    }
+    Ok(if pretty { serde_json::to_string_pretty(&v)? } else { serde_json::to_string(&v)? })
+}
+
+fn write_output(ctx: &Ctx, snapshot_path: &Path, events_path: &Path, st: &UiState) -> Result<()> {
+    // snapshot (atomic)
+    let json = state_json(ctx, st, ctx.cfg.output.pretty_snapshot)?;
+    let tmp = snapshot_path.with_extension("json.tmp");
+    if ctx.cfg.output.fsync {
+        let mut f = File::create(&tmp)?;
+        f.write_all(json.as_bytes())?;
+        f.sync_all()?;
+    } else {
+        fs::write(&tmp, json.as_bytes())?;
+    }
+    fs::rename(&tmp, snapshot_path)?;
+    // Subscribers still get position-only states (see write_state)
+    if events_path.as_os_str().is_empty() || (st.event == "position" && !ctx.cfg.output.events_include_position_only) {
+        return Ok(());
+    }
+    // events (append)
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(events_path)?;
+    let line = state_json(ctx, st, false)?;
+    if ctx.cfg.output.events_timestamp {
+        // Events only; the snapshot stays point-in-time
+        let ts = rfc3339_utc(SystemTime::now());
+        writeln!(f, "{{\"ts\":\"{ts}\",{}", &line[1..])?;
     } else {
-        #[allow(clippy::let_underscore_must_use)]
-        let _ = fs::copy(src, &ctx.current_cover);
+        writeln!(f, "{line}")?;
     }
     Ok(())
 }
 
-// ------------------------- Selection -------------------------
+// Main output, or the extra sink the follower belongs to.
+fn write_follower_state(ctx: &Ctx, extra: Option<&ExtraSink>, st: &UiState) -> Result<()> {
+    extra.map_or_else(
+        || write_state(ctx, st),
+        |sink| {
+            let mut st = st.clone();
+            st.progress_str = progress_str(ctx, &st);
+            st.any_playing = any_playing(ctx);
+            write_extra(ctx, sink, &st)
+        },
+    )
+}
 
-fn recompute_selected(ctx: &Ctx) -> Option<String> {
-    let include = &ctx.cfg.selection.include;
-    let exclude = &ctx.cfg.selection.exclude;
-    let priority = &ctx.cfg.selection.priority;
+// Extra outputs freeze with the main one; each holds back only its newest state.
+fn write_extra(ctx: &Ctx, sink: &ExtraSink, st: &UiState) -> Result<()> {
+    let frozen = ctx.frozen.read().unwrap();
+    if matches!(*frozen, Freeze::On(_)) {
+        *sink.held.lock().unwrap() = Some(st.clone());
+        return Ok(());
+    }
+    drop(frozen);
+    write_output(ctx, &sink.snapshot_path, &sink.events_path, st)
+}
 
-    let players: Vec<String> = ctx
-        .players
-        .read()
-        .unwrap()
+fn any_playing(ctx: &Ctx) -> bool {
+    let sel = &ctx.cfg.selection;
+    let players = ctx.players.read().unwrap();
+    let status = ctx.status.read().unwrap();
+    players
         .iter()
-        .filter(|p| include_exclude_match(p, include, exclude))
-        .cloned()
-        .collect();
+        .filter(|p| include_exclude_match(p, &sel.include, &sel.exclude))
+        .any(|p| status.get(p).is_some_and(|s| is_active_status(ctx, s)))
+}
 
-    if players.is_empty() {
-        return None;
+// Filled in on write, so every path that sets position/length gets it.
+fn progress_str(ctx: &Ctx, st: &UiState) -> String {
+    if st.name.is_empty() {
+        String::new()
+    } else if st.length > 0.0 {
+        ctx.cfg
+            .presentation
+            .progress_format
+            .replace("{position}", &st.position_str)
+            .replace("{length}", &st.length_str)
+    } else {
+        st.position_str.clone()
     }
+}
 
-    let status_map = ctx.status.read().unwrap().clone();
-    let mut playing: Vec<String> = players
-        .iter()
-        .filter(|p| status_map.get(*p).map_or(false, |s| s == "Playing"))
-        .cloned()
-        .collect();
+// ------------------------- Cover Art -------------------------
 
-    let focus = ctx.focus_hint.read().unwrap().clone();
+// Cover size for artWidth/artHeight, measured from the resolved image (not the artUrl, whose
+// download may have failed) and cached per file and mtime. Fallback covers report 0x0.
+fn art_dimensions(ctx: &Ctx, source: &Path) -> (i32, i32) {
+    let fallback = source == ctx.default_cover.as_path()
+        || source == ctx.error_cover.as_path()
+        || source.as_os_str() == ctx.blank_thumbnail.as_str();
+    if fallback {
+        return (0, 0);
+    }
+    let key = (source.to_path_buf(), fs::metadata(source).and_then(|m| m.modified()).ok());
+    let cached = ctx.art_dims.read().unwrap().clone();
+    if let Some((_, dims)) = cached.filter(|(k, _)| *k == key) {
+        return dims;
+    }
+    // Header only, no decode; by content, since cached covers are named .jpg whatever their format
+    let dims = image::ImageReader::open(source)
+        .and_then(image::ImageReader::with_guessed_format)
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+        .and_then(|(w, h)| Some((i32::try_from(w).ok()?, i32::try_from(h).ok()?)))
+        .unwrap_or((0, 0));
+    *ctx.art_dims.write().unwrap() = Some((key, dims));
+    dims
+}
 
-    if !playing.is_empty() {
-        if let Some(f) = &focus {
-            if let Some(p) = playing.iter().find(|pp| pp.starts_with(f)) {
-                return Some(p.clone());
-            }
+// Thumbnail and size for the main output. With art.async_pipeline the cover is resolved by
+// art_worker; until then art.loading_image is shown, or the previous thumbnail (or the blank one) stays.
+async fn main_thumbnail(ctx: &Ctx, player: &str, art_url: &str) -> (String, (i32, i32)) {
+    if !ctx.cfg.art.async_pipeline {
+        return update_art(ctx, player, art_url)
+            .await
+            .unwrap_or_else(|_| (ctx.default_cover.to_string_lossy().to_string(), (0, 0)));
+    }
+    let mut current = ctx.art_current.write().unwrap();
+    if let Some(job) = current.as_ref().filter(|j| j.player == player && j.art_url == art_url) {
+        if let Some(resolved) = job.resolved.clone() {
+            return resolved;
         }
-        for want in priority {
-            if let Some(p) = playing.iter().find(|pp| pp.starts_with(want)) {
-                return Some(p.clone());
-            }
+    } else {
+        let job = ArtJob { player: player.to_string(), art_url: art_url.to_string(), resolved: None };
+        if let Err(e) = ctx.art_tx.try_send(job.clone()) {
+            debug!("art queue: {e}");
+        } else {
+            *current = Some(job);
         }
-        return Some(playing.remove(0));
     }
+    drop(current);
+    if let Some(loading) = &ctx.loading_cover {
+        return (loading.to_string_lossy().to_string(), (0, 0));
+    }
+    ctx.last_state
+        .read()
+        .unwrap()
+        .as_ref()
+        .map_or_else(|| (ctx.blank_thumbnail.clone(), (0, 0)), |st| (st.thumbnail.clone(), (st.art_width, st.art_height)))
+}
 
-    if ctx.cfg.selection.remember_last {
-        if let Some(last) = ctx.last_selected.read().unwrap().clone() {
-            if players.iter().any(|p| *p == last) {
-                return Some(last);
+const ART_QUEUE_LEN: usize = 8;
+
+#[derive(Debug, Clone)]
+struct ArtJob {
+    player: String,
+    art_url: String,
+    resolved: Option<(String, (i32, i32))>, // (thumbnail, (width, height)) once done
+}
+
+fn art_job_current(ctx: &Ctx, job: &ArtJob) -> bool {
+    ctx.art_current
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|j| j.player == job.player && j.art_url == job.art_url)
+}
+
+// art.async_pipeline: resolves queued covers and rewrites the last snapshot with the new thumbnail.
+// Requests queued while a download runs are coalesced; only the newest is processed.
+async fn art_worker(ctx: Arc<Ctx>, mut rx: mpsc::Receiver<ArtJob>) {
+    while let Some(mut job) = rx.recv().await {
+        while let Ok(newer) = rx.try_recv() {
+            job = newer;
+        }
+        if !art_job_current(&ctx, &job) {
+            continue;
+        }
+        let (thumbnail, dims) = update_art(&ctx, &job.player, &job.art_url)
+            .await
+            .unwrap_or_else(|_| (ctx.default_cover.to_string_lossy().to_string(), (0, 0)));
+        let mut current = ctx.art_current.write().unwrap();
+        match current.as_mut() {
+            Some(j) if j.player == job.player && j.art_url == job.art_url => {
+                j.resolved = Some((thumbnail.clone(), dims));
             }
+            _ => continue,
         }
-    }
-    if let Some(f) = &focus {
-        if let Some(p) = players.iter().find(|pp| pp.starts_with(f)) {
-            return Some(p.clone());
+        drop(current);
+        let last = ctx.last_state.read().unwrap().clone();
+        if let Some(mut st) = last.filter(|st| st.name == job.player) {
+            st.thumbnail = thumbnail;
+            (st.art_width, st.art_height) = dims;
+            if let Err(e) = write_state(&ctx, &st) {
+                warn!("write_state error: {e:#}");
+            }
         }
     }
-    for want in priority {
-        if let Some(p) = players.iter().find(|pp| pp.starts_with(want)) {
-            return Some(p.clone());
-        }
+}
+
+// Thumbnail for the main output and the size of the cover behind it.
+async fn update_art(ctx: &Ctx, player: &str, art_url: &str) -> Result<(String, (i32, i32))> {
+    if !ctx.cfg.art.enabled {
+        return Ok((ctx.current_cover.to_string_lossy().to_string(), (0, 0)));
     }
-    if ctx.cfg.selection.fallback == "any" {
-        return Some(players[0].clone());
+    let src = player_art(ctx, player, art_url).await?;
+    let dims = art_dimensions(ctx, &src);
+    if ctx.cfg.art.current_mode_addressing == "content" {
+        // A unique, never-rewritten path per cover: nothing to copy, nothing to cache-bust
+        return Ok((src.to_string_lossy().to_string(), dims));
     }
-    None
+    if let Err(e) = ensure_current_cover(ctx, &src, &ctx.current_cover) {
+        // current_path would show a stale cover; point at the source image instead
+        warn!("{e:#}");
+        return Ok((src.to_string_lossy().to_string(), dims));
+    }
+    Ok((ctx.current_cover.to_string_lossy().to_string(), dims))
 }
 
-// Set selection; returns true if changed, and notifies follower manager via watch channel.
-fn set_selected_sync(ctx: &Ctx, name: Option<String>) -> bool {
-    let mut sel = ctx.selected.write().unwrap();
-    let changed = *sel != name;
-    *sel = name.clone();
-    if let Some(n) = name {
-        *ctx.last_selected.write().unwrap() = Some(n);
+// Cover for one player, shared by the main and extra outputs: resolved, rounded when
+// configured, and copied to art.per_player_current_dir.
+async fn player_art(ctx: &Ctx, player: &str, art_url: &str) -> Result<PathBuf> {
+    let mut src = resolve_art_source(ctx, art_url).await?;
+    if let Some(radius) = ctx.cfg.art.corner_radius.filter(|r| *r > 0) {
+        // Decode, per-pixel pass and PNG encode: keep them off the async workers
+        let (dir, from) = (ctx.http_cache_dir.clone(), src.clone());
+        let rounded = task::spawn_blocking(move || rounded_cover(&dir, &from, radius)).await;
+        match rounded.map_err(anyhow::Error::from).and_then(|r| r) {
+            Ok(rounded) => src = rounded,
+            Err(e) => debug!("corner rounding skipped for {}: {e:#}", src.display()),
+        }
     }
-    if changed {
-        let _ = ctx.sel_tx.send(sel.clone());
+    if let Some(dir) = &ctx.per_player_art_dir {
+        if let Err(e) = ensure_current_cover(ctx, &src, &dir.join(format!("{player}.jpg"))) {
+            warn!("{e:#}");
+        }
     }
-    changed
+    Ok(src)
 }
 
-// Recompute selection and if changed, send quick snapshot immediately.
-fn set_selected_and_kick(ctx: &Arc<Ctx>, name: Option<String>) {
-    let changed = set_selected_sync(ctx, name.clone());
-    if changed {
-        if let Some(n) = name {
-            let ctx2 = ctx.clone();
-            task::spawn(async move { emit_quick_snapshot(ctx2, n).await; });
+// Local file for an artUrl: the file itself, the (downloaded) cache entry, or the default cover.
+async fn resolve_art_source(ctx: &Ctx, art_url: &str) -> Result<PathBuf> {
+    let http_re = Regex::new(r"^https?://").unwrap();
+
+    if art_url.starts_with("file://") {
+        let local_path = Path::new(art_url.trim_start_matches("file://"));
+        if local_path.is_file() {
+            if let Some(dir) = &ctx.embedded_cache_dir {
+                return cache_local_art(dir, art_url, local_path);
+            }
+            return Ok(local_path.to_path_buf());
+        }
+    } else if http_re.is_match(art_url) && ctx.cfg.art.download_http {
+        let mut hasher = Sha1::new();
+        hasher.update(art_url.as_bytes());
+        let fname = format!("{:x}", hasher.finalize());
+        let target = ctx.http_cache_dir.join(format!("{fname}.jpg"));
+        if !target.exists() {
+            if art_url_blacklisted(ctx, art_url) {
+                return Ok(ctx.error_cover.clone());
+            }
+            if let Err(e) = download_art_with_retries(ctx, art_url, &target).await {
+                debug!("art download failed for {art_url}: {e:#}");
+                record_art_failure(ctx, art_url);
+                return Ok(ctx.error_cover.clone());
+            }
+            ctx.art_failures.write().unwrap().remove(art_url);
+        }
+        if target.exists() {
+            return Ok(target);
         }
     }
+
+    Ok(ctx.default_cover.clone())
 }
 
-// ------------------------- Follower (playerctl -F) -------------------------
+// A URL that failed art.fail_threshold times is skipped until art.fail_ttl_secs after its last failure.
+fn art_url_blacklisted(ctx: &Ctx, art_url: &str) -> bool {
+    let threshold = ctx.cfg.art.fail_threshold;
+    let ttl = Duration::from_secs(ctx.cfg.art.fail_ttl_secs);
+    threshold > 0
+        && ctx
+            .art_failures
+            .read()
+            .unwrap()
+            .get(art_url)
+            .is_some_and(|(count, last)| *count >= threshold && last.elapsed() < ttl)
+}
 
-// Read capabilities (CanGoNext/Previous) once per track/status change (via busctl; cheap).
-async fn get_caps_dbus(simple_name: &str) -> (i32, i32) {
-    let busname = format!("org.mpris.MediaPlayer2.{simple_name}");
-    let outn = Command::new("busctl")
-        .arg("--user")
-        .arg("get-property")
-        .arg(&busname)
-        .arg("/org/mpris/MediaPlayer2")
-        .arg("org.mpris.MediaPlayer2.Player")
-        .arg("CanGoNext")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .await;
-    let outp = Command::new("busctl")
+fn record_art_failure(ctx: &Ctx, art_url: &str) {
+    let ttl = Duration::from_secs(ctx.cfg.art.fail_ttl_secs);
+    let now = Instant::now();
+    let mut failures = ctx.art_failures.write().unwrap();
+    failures.retain(|_, (_, last)| last.elapsed() < ttl);
+    let entry = failures.entry(art_url.to_string()).or_insert((0, now));
+    *entry = (entry.0 + 1, now);
+    let count = entry.0;
+    drop(failures);
+    if count == ctx.cfg.art.fail_threshold {
+        warn!("art URL {art_url} failed {count} times, skipping it for {}s", ttl.as_secs());
+    }
+}
+
+const ART_RETRY_DELAY_MS: u64 = 250;
+
+// art.download_retries extra attempts, each bounded by art.timeout_ms; art.total_timeout_ms caps
+// the whole thing, cutting off an attempt in progress and skipping the remaining retries.
+async fn download_art_with_retries(ctx: &Ctx, art_url: &str, target: &Path) -> Result<()> {
+    let attempts = async {
+        let mut retries_left = ctx.cfg.art.download_retries;
+        loop {
+            match download_art(ctx, art_url, target).await {
+                Err(e) if retries_left > 0 => {
+                    debug!("art download failed for {art_url}: {e:#}, retrying");
+                    retries_left -= 1;
+                    tokio::time::sleep(Duration::from_millis(ART_RETRY_DELAY_MS)).await;
+                }
+                res => return res,
+            }
+        }
+    };
+    match ctx.cfg.art.total_timeout_ms {
+        Some(ms) => tokio::time::timeout(Duration::from_millis(ms), attempts)
+            .await
+            .map_err(|_| anyhow::anyhow!("art.total_timeout_ms ({ms}ms) exceeded"))?,
+        None => attempts.await,
+    }
+}
+
+async fn download_art(ctx: &Ctx, art_url: &str, target: &Path) -> Result<()> {
+    let _inflight = InflightGuard::new(&ctx.art_inflight);
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(ctx.cfg.art.timeout_ms))
+        .build()?;
+    let resp = client.get(art_url).send().await?.error_for_status()?;
+    let bytes = resp.bytes().await.unwrap_or(Bytes::new());
+    anyhow::ensure!(!bytes.is_empty(), "empty response");
+    // Partial downloads stay identifiable as *.part until complete.
+    let part = target.with_extension("jpg.part");
+    fs::write(&part, &bytes)?;
+    fs::rename(&part, target)?;
+    Ok(())
+}
+
+// Copy of a local (often temporary, extracted-from-file) cover in the embedded cache.
+// Keyed by URL + mtime, so a path reused for a new cover gets a new entry.
+fn cache_local_art(dir: &Path, art_url: &str, local_path: &Path) -> Result<PathBuf> {
+    let mtime = fs::metadata(local_path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{art_url}@{mtime}").as_bytes());
+    let target = dir.join(format!("{:x}.jpg", hasher.finalize()));
+    if !target.exists() {
+        let part = target.with_extension("jpg.part");
+        fs::copy(local_path, &part)?;
+        fs::rename(&part, &target)?;
+    }
+    Ok(target)
+}
+
+// art.corner_radius: PNG copy of `src` with transparent rounded corners, cached by path + mtime + radius.
+fn rounded_cover(dir: &Path, src: &Path, radius: u32) -> Result<PathBuf> {
+    let mtime = fs::metadata(src)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}@{mtime}@{radius}", src.display()).as_bytes());
+    let target = dir.join(format!("{:x}.png", hasher.finalize()));
+    if !target.exists() {
+        let mut img = image::open(src)?.into_rgba8();
+        round_corners(&mut img, radius);
+        let part = target.with_extension("png.part");
+        img.save_with_format(&part, image::ImageFormat::Png)?;
+        fs::rename(&part, &target)?;
+    }
+    Ok(target)
+}
+
+// Anti-aliased: edge pixels get partial alpha by their distance to the corner arc.
+fn round_corners(img: &mut image::RgbaImage, radius: u32) {
+    let (w, h) = img.dimensions();
+    let r = radius.min(w / 2).min(h / 2);
+    if r == 0 {
+        return;
+    }
+    let rf = f64::from(r);
+    for y in 0..h {
+        for x in 0..w {
+            // Distance into the corner square, measured from the arc's center
+            let dx = if x < r { rf - f64::from(x) - 0.5 } else if x >= w - r { f64::from(x - (w - r)) + 0.5 } else { continue };
+            let dy = if y < r { rf - f64::from(y) - 0.5 } else if y >= h - r { f64::from(y - (h - r)) + 0.5 } else { continue };
+            let coverage = (rf - dx.hypot(dy) + 0.5).clamp(0.0, 1.0);
+            let px = img.get_pixel_mut(x, y);
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)] // coverage is within 0..=1
+            let alpha = (f64::from(px[3]) * coverage).round() as u8;
+            px[3] = alpha;
+        }
+    }
+}
+
+// Counts in-flight art downloads so shutdown can wait for them.
+struct InflightGuard<'a>(&'a AtomicUsize);
+impl<'a> InflightGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+impl Drop for InflightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn remove_partial_downloads(ctx: &Ctx) {
+    let dirs = std::iter::once(&ctx.http_cache_dir).chain(ctx.embedded_cache_dir.as_ref());
+    for entries in dirs.filter_map(|d| fs::read_dir(d).ok()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "part") {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+// Point `target` at `src`: symlink, or copy via a temp file + rename so readers never see a partial image.
+fn ensure_current_cover(ctx: &Ctx, src: &Path, target: &Path) -> Result<()> {
+    let res = (|| -> std::io::Result<()> {
+        if let Some(p) = target.parent() {
+            fs::create_dir_all(p)?;
+        }
+        if ctx.cfg.art.use_symlink {
+            if target.symlink_metadata().is_ok() {
+                fs::remove_file(target)?;
+            }
+            std::os::unix::fs::symlink(src, target)
+        } else {
+            let mut tmp = target.as_os_str().to_owned();
+            tmp.push(".tmp");
+            fs::copy(src, &tmp)?;
+            fs::rename(&tmp, target)
+        }
+    })();
+    res.with_context(|| format!("cannot write cover {}", target.display()))
+}
+
+// ------------------------- Selection -------------------------
+
+fn mpris_bus_name(simple_name: &str) -> String {
+    format!("org.mpris.MediaPlayer2.{simple_name}")
+}
+
+// Longest matching per-player prefix wins; unset glyphs fall back to the global icons.
+fn status_icon(p: &Presentation, player: &str, status: &str) -> String {
+    p.player_status_icons
+        .iter()
+        .filter(|(prefix, _)| player.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .and_then(|(_, icons)| icons.get(status))
+        .or_else(|| p.status_icons.get(status))
+        .unwrap_or_default()
+        .to_string()
+}
+
+// selection.player_args for `player` (longest matching prefix), passed to playerctl verbatim.
+fn player_args<'a>(ctx: &'a Ctx, player: &str) -> &'a [String] {
+    ctx.cfg
+        .selection
+        .player_args
+        .iter()
+        .filter(|(prefix, _)| player.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(&[], |(_, args)| args.as_slice())
+}
+
+// `playerctl [player_args] -p <player>`, ready for the subcommand.
+fn playerctl(ctx: &Ctx, player: &str) -> Command {
+    let mut cmd = Command::new("playerctl");
+    cmd.args(player_args(ctx, player)).arg("-p").arg(player);
+    cmd
+}
+
+fn is_active_status(ctx: &Ctx, status: &str) -> bool {
+    ctx.cfg.selection.active_statuses.iter().any(|s| s == status)
+}
+
+// Canonical lowercase status; anything counted as active (see `active_statuses`) is "playing".
+fn normalize_status(ctx: &Ctx, raw: &str) -> String {
+    if raw.is_empty() {
+        String::new()
+    } else if is_active_status(ctx, raw) || raw.eq_ignore_ascii_case("playing") {
+        "playing".into()
+    } else if raw.eq_ignore_ascii_case("paused") {
+        "paused".into()
+    } else {
+        "stopped".into()
+    }
+}
+
+// Pinned prefix from IPC "pin", ignored once its TTL has passed.
+fn active_pin(ctx: &Ctx) -> Option<String> {
+    let pinned = ctx.pinned.read().unwrap();
+    pinned
+        .as_ref()
+        .filter(|(_, expiry)| *expiry > Instant::now())
+        .map(|(prefix, _)| prefix.clone())
+}
+
+// Records when each player entered Playing and orders `playing` most recent first, so every
+// tier below prefers the player you just started among otherwise equal candidates.
+fn order_by_playing_since(ctx: &Ctx, playing: &mut [String]) {
+    let mut since = ctx.playing_since.write().unwrap();
+    since.retain(|p, _| playing.contains(p));
+    for p in playing.iter() {
+        since.entry(p.clone()).or_insert_with(Instant::now);
+    }
+    playing.sort_by(|a, b| since[b].cmp(&since[a]).then_with(|| a.cmp(b)));
+    drop(since);
+}
+
+const WATCHDOG_SECS: u64 = 2;
+
+fn is_idle(ctx: &Ctx) -> bool {
+    let after = ctx.cfg.idle.after_secs;
+    after > 0 && ctx.last_playing.read().unwrap().elapsed() >= Duration::from_secs(after)
+}
+
+// Called on every recompute; waking from idle snaps the watchdogs back to the fast cadence.
+fn note_playback(ctx: &Ctx, any_playing: bool) {
+    if !any_playing {
+        return;
+    }
+    let was_idle = is_idle(ctx);
+    *ctx.last_playing.write().unwrap() = Instant::now();
+    if was_idle {
+        debug!("playback resumed, leaving idle mode");
+        ctx.idle_wake.notify_waiters();
+    }
+}
+
+fn watchdog_period(ctx: &Ctx) -> Duration {
+    if is_idle(ctx) {
+        Duration::from_secs(ctx.cfg.idle.watchdog_secs.max(WATCHDOG_SECS))
+    } else {
+        Duration::from_secs(WATCHDOG_SECS)
+    }
+}
+
+// Application names/binaries (lowercase) of sink inputs that are neither corked nor muted,
+// from `pactl list sink-inputs`.
+fn parse_audible_apps(text: &str) -> Vec<String> {
+    let mut apps = Vec::new();
+    for block in text.split("Sink Input #").skip(1) {
+        let mut silent = false;
+        let mut names = Vec::new();
+        for line in block.lines().map(str::trim) {
+            if line == "Corked: yes" || line == "Mute: yes" {
+                silent = true;
+            }
+            for key in ["application.name = ", "application.process.binary = "] {
+                if let Some(v) = line.strip_prefix(key) {
+                    names.push(v.trim_matches('"').to_lowercase());
+                }
+            }
+        }
+        if !silent {
+            apps.extend(names);
+        }
+    }
+    apps
+}
+
+// Heuristic: "firefox.instance_1_240" matches "firefox"; "mpv" matches "mpv media player".
+fn is_audible(player: &str, apps: &[String]) -> bool {
+    let base = player.split('.').next().unwrap_or(player).to_lowercase();
+    !base.is_empty() && apps.iter().any(|a| a.contains(&base) || (!a.is_empty() && base.contains(a.as_str())))
+}
+
+// require_audio: drop "ghost" players (playing but silent/muted) when any playing player is audible.
+fn prefer_audible(ctx: &Ctx, playing: &mut Vec<String>) {
+    if !ctx.cfg.selection.require_audio {
+        return;
+    }
+    let apps = ctx.audible_apps.read().unwrap().clone();
+    let Some(apps) = apps else {
+        return;
+    };
+    if playing.iter().any(|p| is_audible(p, &apps)) {
+        playing.retain(|p| is_audible(p, &apps));
+    }
+}
+
+// The chosen player and why it won (exposed as `selectionReason`).
+fn recompute_selected(ctx: &Ctx) -> Option<(String, &'static str)> {
+    let include = &ctx.cfg.selection.include;
+    let exclude = &ctx.cfg.selection.exclude;
+    let priority = &ctx.cfg.selection.priority;
+
+    let players: Vec<String> = ctx
+        .players
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|p| include_exclude_match(p, include, exclude))
+        .cloned()
+        .collect();
+
+    if players.is_empty() {
+        return None;
+    }
+
+    let status_map = ctx.status.read().unwrap().clone();
+    let mut playing: Vec<String> = players
+        .iter()
+        .filter(|p| status_map.get(*p).is_some_and(|s| is_active_status(ctx, s)))
+        .cloned()
+        .collect();
+    note_playback(ctx, !playing.is_empty());
+    order_by_playing_since(ctx, &mut playing);
+    prefer_audible(ctx, &mut playing);
+
+    let lock = ctx.selection_lock.read().unwrap().clone();
+    if let Some((locked, _)) = lock.filter(|(p, expiry)| *expiry > Instant::now() && players.contains(p)) {
+        return Some((locked, "locked"));
+    }
+
+    if ctx.cfg.selection.sticky {
+        let current = ctx.selected.read().unwrap().clone();
+        if let Some(cur) = current {
+            if playing.contains(&cur) {
+                return Some((cur, "sticky"));
+            }
+        }
+    }
+
+    // Playback-driven focus: the player that most recently started playing. An approximation
+    // for compositors without a focus stream (e.g. GNOME); derived here, focus_hint stays Hyprland's.
+    let focus = if ctx.cfg.selection.focus_source == "playback" {
+        playing.first().cloned()
+    } else {
+        ctx.focus_hint.read().unwrap().clone()
+    };
+    let pin = active_pin(ctx);
+
+    if ctx.cfg.selection.policy == "weighted" {
+        return weighted_choice(ctx, &players, &playing, focus.as_deref(), pin.as_deref());
+    }
+
+    if !playing.is_empty() {
+        if let Some(f) = &focus {
+            if let Some(p) = playing.iter().find(|pp| pp.starts_with(f)) {
+                return Some((p.clone(), "focus"));
+            }
+        }
+        if let Some(p) = pin.as_ref().and_then(|want| playing.iter().find(|pp| pp.starts_with(want))) {
+            return Some((p.clone(), "pinned"));
+        }
+        for want in priority {
+            if let Some(p) = playing.iter().find(|pp| pp.starts_with(want)) {
+                return Some((p.clone(), "priority"));
+            }
+        }
+        return Some((playing.remove(0), "playing-only"));
+    }
+
+    if ctx.cfg.selection.remember_last {
+        let last = ctx.last_selected.read().unwrap().clone();
+        if let Some(last) = last {
+            if players.contains(&last) {
+                return Some((last, "remember_last"));
+            }
+        }
+    }
+    if let Some(f) = &focus {
+        if let Some(p) = players.iter().find(|pp| pp.starts_with(f)) {
+            return Some((p.clone(), "focus"));
+        }
+    }
+    if let Some(p) = pin.as_ref().and_then(|want| players.iter().find(|pp| pp.starts_with(want))) {
+        return Some((p.clone(), "pinned"));
+    }
+    for want in priority {
+        if let Some(p) = players.iter().find(|pp| pp.starts_with(want)) {
+            return Some((p.clone(), "priority"));
+        }
+    }
+    if ctx.cfg.selection.fallback == "any" {
+        return Some((players[0].clone(), "fallback-any"));
+    }
+    None
+}
+
+const SCORE_PLAYING: i64 = 10;
+const SCORE_FOCUS: i64 = 5;
+const SCORE_PIN: i64 = 1000;
+const SCORE_RECENT: i64 = 1;
+
+// selection.policy = "weighted": the highest score wins, ties by name.
+//   score = 10 if playing + 5 if focused + weight (longest matching prefix in selection.weights, else 1)
+//         + 1 for recency (the most recently started playing player; with nothing playing, the
+//           previous selection if remember_last) + 1000 if pinned
+// With nothing playing and fallback = "none", only players the ordered policy could also pick
+// (remembered, focused, pinned or in `priority`) are candidates.
+// `playing` is ordered most recently started first.
+fn weighted_choice(
+    ctx: &Ctx,
+    players: &[String],
+    playing: &[String],
+    focus: Option<&str>,
+    pin: Option<&str>,
+) -> Option<(String, &'static str)> {
+    let recent = if playing.is_empty() {
+        ctx.cfg
+            .selection
+            .remember_last
+            .then(|| ctx.last_selected.read().unwrap().clone())
+            .flatten()
+    } else {
+        playing.first().cloned()
+    };
+    let score = |p: &String| {
+        let weight = ctx
+            .cfg
+            .selection
+            .weights
+            .iter()
+            .filter(|(prefix, _)| p.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(1, |(_, w)| *w);
+        weight
+            + if playing.contains(p) { SCORE_PLAYING } else { 0 }
+            + if focus.is_some_and(|f| p.starts_with(f)) { SCORE_FOCUS } else { 0 }
+            + if recent.as_ref() == Some(p) { SCORE_RECENT } else { 0 }
+            + if pin.is_some_and(|want| p.starts_with(want)) { SCORE_PIN } else { 0 }
+    };
+    let gated = playing.is_empty() && ctx.cfg.selection.fallback != "any";
+    let eligible = |p: &&String| {
+        !gated
+            || recent.as_ref() == Some(*p)
+            || focus.is_some_and(|f| p.starts_with(f))
+            || pin.is_some_and(|want| p.starts_with(want))
+            || ctx.cfg.selection.priority.iter().any(|want| p.starts_with(want.as_str()))
+    };
+    players
+        .iter()
+        .filter(eligible)
+        .max_by(|a, b| score(a).cmp(&score(b)).then_with(|| b.cmp(a)))
+        .map(|p| (p.clone(), "weighted"))
+}
+
+// Set selection; returns true if changed, and notifies follower manager via watch channel.
+fn set_selected_sync(ctx: &Ctx, choice: Option<(String, &'static str)>) -> bool {
+    let (name, reason) = choice.map_or((None, ""), |(n, r)| (Some(n), r));
+    *ctx.selection_reason.write().unwrap() = reason;
+    let mut sel = ctx.selected.write().unwrap();
+    let changed = *sel != name;
+    sel.clone_from(&name);
+    if let Some(n) = name {
+        *ctx.last_selected.write().unwrap() = Some(n);
+    }
+    if changed {
+        let _ = ctx.sel_tx.send(sel.clone());
+    }
+    changed
+}
+
+// Recompute selection and if changed, send quick snapshot immediately.
+fn set_selected_and_kick(ctx: &Arc<Ctx>, choice: Option<(String, &'static str)>) {
+    let name = choice.as_ref().map(|(n, _)| n.clone());
+    let changed = set_selected_sync(ctx, choice);
+    if changed {
+        if let Some(n) = name {
+            let ctx2 = ctx.clone();
+            task::spawn(async move { emit_quick_snapshot(ctx2, n).await; });
+        }
+    }
+}
+
+// ------------------------- Follower (playerctl -F) -------------------------
+
+// Held while a query subprocess runs; the semaphore is never closed.
+async fn query_permit(ctx: &Ctx) -> SemaphorePermit<'_> {
+    ctx.query_slots.acquire().await.expect("query semaphore closed")
+}
+
+// One boolean Player property via busctl; None when unreadable.
+// `iface`: "org.mpris.MediaPlayer2.Player", or the root "org.mpris.MediaPlayer2".
+async fn busctl_player_bool(busname: &str, iface: &str, prop: &str) -> Option<bool> {
+    let out = Command::new("busctl")
         .arg("--user")
         .arg("get-property")
-        .arg(&busname)
+        .arg(busname)
         .arg("/org/mpris/MediaPlayer2")
-        .arg("org.mpris.MediaPlayer2.Player")
-        .arg("CanGoPrevious")
+        .arg(iface)
+        .arg(prop)
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output()
-        .await;
+        .await
+        .ok()?;
+    let s = String::from_utf8_lossy(&out.stdout);
+    if s.contains("b true") {
+        Some(true)
+    } else if s.contains("b false") {
+        Some(false)
+    } else {
+        None
+    }
+}
 
-    let s_n = outn
-        .ok()
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .unwrap_or_default();
-    let s_p = outp
-        .ok()
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .unwrap_or_default();
-    (i32::from(s_n.contains("b true")), i32::from(s_p.contains("b true")))
+// Read capabilities (CanGoNext/Previous/CanControl) and the root Fullscreen property once per
+// track/status change (via busctl; cheap). Unreadable CanControl counts as controllable.
+async fn get_caps_dbus(ctx: &Ctx, simple_name: &str) -> (i32, i32, i32, bool) {
+    let _permit = query_permit(ctx).await;
+    let busname = mpris_bus_name(simple_name);
+    let player = "org.mpris.MediaPlayer2.Player";
+    let next = busctl_player_bool(&busname, player, "CanGoNext").await.unwrap_or(false);
+    let prev = busctl_player_bool(&busname, player, "CanGoPrevious").await.unwrap_or(false);
+    let control = busctl_player_bool(&busname, player, "CanControl").await.unwrap_or(true);
+    let fullscreen = busctl_player_bool(&busname, "org.mpris.MediaPlayer2", "Fullscreen").await.unwrap_or(false);
+    (i32::from(next), i32::from(prev), i32::from(control), fullscreen)
+}
+
+// Where the media comes from, by xesam:url (`sourceKind`):
+// "youtube" | "file" | "http-stream" (other http/https) | "local" (no url) | "other"
+fn source_kind(url: &str) -> &'static str {
+    if url.is_empty() {
+        "local"
+    } else if url.contains("youtube.com/watch") || url.contains("music.youtube.com") || url.contains("youtu.be/") {
+        "youtube"
+    } else if url.starts_with("file://") {
+        "file"
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        "http-stream"
+    } else {
+        "other"
+    }
 }
 
 // Override policy for YouTube in Firefox: no playlist => only next enabled.
 fn override_caps_for_youtube(simple_name: &str, url: &str, can_next: i32, can_prev: i32) -> (i32, i32) {
     let is_firefox = simple_name.starts_with("firefox");
-    let is_yt = url.contains("youtube.com/watch") || url.contains("music.youtube.com");
-    if is_firefox && is_yt {
+    if is_firefox && source_kind(url) == "youtube" {
         let has_playlist = url.contains("list=");
         if !has_playlist {
             return (1, 0);
@@ -588,20 +1807,69 @@ fn override_caps_for_youtube(simple_name: &str, url: &str, can_next: i32, can_pr
     (can_next, can_prev)
 }
 
-async fn spawn_follower(ctx: Arc<Ctx>, name: String) -> Result<Child> {
-    // Initial blank snapshot with name (instant UI switch)
-    {
-        let mut st = UiState::empty(&ctx.default_cover.to_string_lossy());
-        st.name = name.clone();
-        write_state(&ctx, &st).await?;
+// Generation of the main follower, or of the sink's follower for `extra`.
+fn follower_generation(ctx: &Ctx, extra: Option<&ExtraSink>) -> u64 {
+    extra.map_or(&ctx.follower_gen, |sink| &sink.generation).load(Ordering::SeqCst)
+}
+
+// `generation`: the follower that reports; stale ones (already replaced) are ignored.
+fn set_follower_alive(ctx: &Ctx, extra: Option<&ExtraSink>, generation: u64, alive: bool) {
+    if follower_generation(ctx, extra) != generation {
+        return;
+    }
+    if let Some(sink) = extra {
+        sink.alive.store(alive, Ordering::SeqCst);
+    } else {
+        if alive {
+            *ctx.follower_heartbeat.write().unwrap() = Instant::now();
+        }
+        ctx.follower_alive.store(alive, Ordering::SeqCst);
     }
+}
 
-    let mut child = Command::new("playerctl")
-        .arg("-p")
-        .arg(&name)
+// Shared by the follower and the quick snapshot. The URL goes last: it may contain '|'.
+const METADATA_FORMAT: &str =
+    "{{status}}|{{playerName}}|{{title}}|{{artist}}|{{mpris:length}}|{{mpris:artUrl}}|{{position}}|{{xesam:album}}|{{xesam:albumArtist}}|{{xesam:url}}";
+const METADATA_FIELDS: usize = 10;
+
+// Classical/compilation tracks often only carry xesam:albumArtist.
+fn metadata_artist(ctx: &Ctx, parts: &[String]) -> String {
+    let (artist, album_artist) = (&parts[3], &parts[8]);
+    if artist.is_empty() && ctx.cfg.presentation.artist_fallback_album_artist {
+        album_artist.clone()
+    } else {
+        artist.clone()
+    }
+}
+
+// `trackHash`: content identity for scrobbler dedup; mpris:trackid changes per session.
+// Empty without a title (nothing playing).
+fn track_hash(title: &str, artist: &str, album: &str, len_us: &str) -> String {
+    if title.is_empty() {
+        return String::new();
+    }
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{title}|{artist}|{album}|{len_us}").as_bytes());
+    format!("{:x}", hasher.finalize())[..12].to_string()
+}
+
+// `extra`: follow into an `[[output.extra]]` sink instead of the main output.
+fn spawn_follower(ctx: Arc<Ctx>, name: String, extra: Option<Arc<ExtraSink>>) -> Result<Child> {
+    // Initial blank snapshot with name (instant UI switch), unless this player is already shown
+    let shown = extra.is_none() && ctx.last_state.read().unwrap().as_ref().is_some_and(|st| st.name == name);
+    if !shown {
+        let mut st = UiState::empty(&ctx.blank_thumbnail);
+        st.name.clone_from(&name);
+        match &extra {
+            Some(sink) => write_extra(&ctx, sink, &st)?,
+            None => write_placeholder(&ctx, &st)?,
+        }
+    }
+
+    let mut child = playerctl(&ctx, &name)
         .arg("metadata")
         .arg("--format")
-        .arg("{{status}}|{{playerName}}|{{title}}|{{artist}}|{{mpris:length}}|{{mpris:artUrl}}|{{position}}|{{xesam:url}}")
+        .arg(METADATA_FORMAT)
         .arg("-F")
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
@@ -609,12 +1877,11 @@ async fn spawn_follower(ctx: Arc<Ctx>, name: String) -> Result<Child> {
         .context("spawn playerctl -F")?;
 
     let stdout = child.stdout.take().context("follower stdout")?;
-    let mut lines = BufReader::new(stdout).lines();
+    let mut reader = BufReader::new(stdout);
 
-    ctx.follower_alive.store(true, Ordering::SeqCst);
+    let generation = follower_generation(&ctx, extra.as_deref());
+    set_follower_alive(&ctx, extra.as_deref(), generation, true);
 
-    let ctx_clone = ctx.clone();
-    let name_clone = name.clone();
     task::spawn(async move {
         // Local buffers to avoid excess busctl calls
         let mut last_status = String::new();
@@ -623,54 +1890,71 @@ async fn spawn_follower(ctx: Arc<Ctx>, name: String) -> Result<Child> {
         let mut last_url = String::new();
         let mut last_can_next = 0;
         let mut last_can_prev = 0;
+        let mut last_can_control = 1;
+        let mut last_fullscreen = false;
+        // Baseline track for notifications; the first line (selection switch) doesn't notify
+        let mut notified_track: Option<(String, String)> = None;
 
-        while let Ok(Some(l)) = lines.next_line().await {
-            let parts: Vec<_> = l.splitn(8, '|').map(|s| s.trim().to_string()).collect();
-            if parts.len() != 8 {
+        let mut buf = Vec::new();
+        while let Ok(Some(l)) = next_line_lossy(&mut reader, &mut buf).await {
+            if extra.is_none() {
+                *ctx.follower_heartbeat.write().unwrap() = Instant::now();
+            }
+            let parts: Vec<_> = l.splitn(METADATA_FIELDS, '|').map(|s| s.trim().to_string()).collect();
+            if parts.len() != METADATA_FIELDS {
                 continue;
             }
 
             let status = parts[0].clone();
             let title = parts[2].clone();
-            let artist = parts[3].clone();
+            let artist = metadata_artist(&ctx, &parts);
             let len_us = parts[4].clone();
             let art = parts[5].clone();
             let pos_us = parts[6].clone(); // microseconds
-            let url = parts[7].clone();
+            let url = parts[9].clone();
 
             // Update status map (helps selection policy)
             {
-                ctx_clone
+                ctx
                     .status
                     .write()
                     .unwrap()
-                    .insert(name_clone.clone(), status.clone());
+                    .insert(name.clone(), status.clone());
             }
 
             // Capabilities refresh on meaningful changes
             let mut can_next = last_can_next;
             let mut can_prev = last_can_prev;
+            let mut can_control = last_can_control;
+            let mut fullscreen = last_fullscreen;
             if status != last_status || title != last_title || artist != last_artist || url != last_url {
-                let (n, p) = get_caps_dbus(&name_clone).await;
-                let (n, p) = override_caps_for_youtube(&name_clone, &url, n, p);
+                let (n, p, c, f) = get_caps_dbus(&ctx, &name).await;
+                let (n, p) = override_caps_for_youtube(&name, &url, n, p);
                 can_next = n;
                 can_prev = p;
+                can_control = c;
+                fullscreen = f;
                 last_can_next = n;
                 last_can_prev = p;
+                last_can_control = c;
+                last_fullscreen = f;
                 last_status = status.clone();
                 last_title = title.clone();
                 last_artist = artist.clone();
                 last_url = url.clone();
             }
 
-            let mut st = UiState::empty(&ctx_clone.default_cover.to_string_lossy());
-            st.name = name_clone.clone();
-            st.status = status;
-            st.title = truncate(&title, ctx_clone.cfg.presentation.truncate_title);
-            st.artist = truncate(&artist, ctx_clone.cfg.presentation.truncate_artist);
+            let mut st = UiState::empty(&ctx.blank_thumbnail);
+            st.name = name.clone();
+            st.bus_name = mpris_bus_name(&name);
+            st.status = normalize_status(&ctx, &status);
+            st.status_icon = status_icon(&ctx.cfg.presentation, &name, &st.status);
+            st.status_raw = status;
+            (st.title, st.artist) = truncate_title_artist(&ctx, &title, &artist);
+            st.track_hash = track_hash(&title, &artist, &parts[7], &len_us);
 
             if let Ok(us) = len_us.parse::<u64>() {
-                st.length = (us as f64) / 1_000_000.0;
+                st.length = Duration::from_micros(us).as_secs_f64();
                 st.length_str = fmt_time(st.length);
             }
 
@@ -679,63 +1963,154 @@ async fn spawn_follower(ctx: Arc<Ctx>, name: String) -> Result<Child> {
                 let pos = usf / 1_000_000.0;
                 st.position = pos;
                 st.position_str = fmt_time(pos);
+                st.position_ts = epoch_secs();
             }
 
-            st.thumbnail = update_art(&ctx_clone, &art)
-                .await
-                .unwrap_or_else(|_| ctx_clone.default_cover.to_string_lossy().to_string());
+            // Extra outputs point at the cached art directly; current_path belongs to the main output.
+            if extra.is_some() {
+                let src = player_art(&ctx, &name, &art).await.unwrap_or_else(|_| ctx.default_cover.clone());
+                (st.art_width, st.art_height) = art_dimensions(&ctx, &src);
+                st.thumbnail = src.to_string_lossy().to_string();
+            } else {
+                (st.thumbnail, (st.art_width, st.art_height)) = main_thumbnail(&ctx, &name, &art).await;
+            }
 
             st.can_next = can_next;
             st.can_prev = can_prev;
+            st.can_control = can_control;
+            st.fullscreen = fullscreen;
+            st.source_kind = source_kind(&url).into();
 
-            if let Err(e) = write_state(&ctx_clone, &st).await {
-                eprintln!("mpris-bridge: write_state error: {e:#}");
+            if extra.is_none() && ctx.cfg.output.notify_on_track_change && !title.is_empty() {
+                let track = (title.clone(), artist.clone());
+                if notified_track.as_ref().is_some_and(|t| *t != track) {
+                    notify_track_change(&ctx, &title, &artist, &st.thumbnail);
+                }
+                notified_track = Some(track);
+            }
+
+            // Replaced while resolving: the lines still buffered belong to the old player
+            if follower_generation(&ctx, extra.as_deref()) != generation {
+                break;
+            }
+            if let Err(e) = write_follower_state(&ctx, extra.as_deref(), &st) {
+                warn!("write_state error: {e:#}");
             }
         }
-        ctx_clone.follower_alive.store(false, Ordering::SeqCst);
+        set_follower_alive(&ctx, extra.as_deref(), generation, false);
     });
 
     Ok(child)
 }
 
+const NOTIFY_DEBOUNCE_MS: u64 = 750;
+
+// Debounced: skipping through several tracks quickly only notifies the last one.
+fn notify_track_change(ctx: &Arc<Ctx>, title: &str, artist: &str, icon: &str) {
+    let seq = ctx.notify_seq.fetch_add(1, Ordering::SeqCst) + 1;
+    let ctx = ctx.clone();
+    let (title, artist, icon) = (title.to_string(), artist.to_string(), icon.to_string());
+    task::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(NOTIFY_DEBOUNCE_MS)).await;
+        if ctx.notify_seq.load(Ordering::SeqCst) != seq {
+            return;
+        }
+        let res = Command::new("notify-send")
+            .arg("--app-name=mpris-bridge")
+            .arg(format!("--icon={icon}"))
+            .arg(format!("--expire-time={}", ctx.cfg.output.notify_timeout_ms))
+            .arg("--") // titles may start with '-'
+            .arg(&title)
+            .arg(&artist)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if let Err(e) = res {
+            debug!("notify-send failed: {e:#}");
+        }
+    });
+}
+
+// Like `lines()`, but a mis-encoded title is decoded lossily instead of ending the stream.
+async fn next_line_lossy<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> std::io::Result<Option<String>> {
+    buf.clear();
+    if reader.read_until(b'\n', buf).await? == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(buf);
+    Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+// `playerctl -F` prints a line every second while playing (position is in the format),
+// so silence while the player plays means the follower is wedged.
+fn follower_stalled(ctx: &Ctx, player: &str) -> bool {
+    let stall_ms = ctx.cfg.selection.follower_stall_ms;
+    if stall_ms == 0 {
+        return false;
+    }
+    let playing = ctx.status.read().unwrap().get(player).is_some_and(|s| is_active_status(ctx, s));
+    let silent = ctx.follower_heartbeat.read().unwrap().elapsed();
+    if playing && silent >= Duration::from_millis(stall_ms) {
+        warn!("follower for {player} stalled ({}s without output while playing), respawning", silent.as_secs());
+        return true;
+    }
+    false
+}
+
+// Kills and reaps the running main follower before spawning the next one, so at most one
+// is ever alive. Bumping the generation first keeps the old reader's exit from clearing
+// `follower_alive` for its replacement (which made the watchdog respawn right after a switch).
+async fn replace_follower<F>(ctx: &Ctx, slot: &mut Option<Child>, name: Option<String>, spawn: F)
+where
+    F: FnOnce(String) -> Result<Child>,
+{
+    ctx.follower_gen.fetch_add(1, Ordering::SeqCst);
+    ctx.follower_alive.store(false, Ordering::SeqCst);
+    if let Some(mut ch) = slot.take() {
+        // kill() also waits, so the process is gone before the next spawn
+        if let Err(e) = ch.kill().await {
+            debug!("kill follower: {e:#}");
+        }
+        ctx.followers_live.fetch_sub(1, Ordering::SeqCst);
+    }
+    if let Some(name) = name {
+        match spawn(name) {
+            Ok(child) => {
+                *slot = Some(child);
+                let live = ctx.followers_live.fetch_add(1, Ordering::SeqCst) + 1;
+                debug_assert!(live <= 1, "{live} main followers alive");
+            }
+            Err(e) => error!("spawn follower failed: {e:#}"),
+        }
+    }
+}
+
 // Watchdog + reactive follower manager
 async fn follower_manager(ctx: Arc<Ctx>, mut rx: watch::Receiver<Option<String>>) -> Result<()> {
-    use tokio::time::interval;
     let mut current: Option<String> = None;
     let mut child_opt: Option<Child> = None;
-    let mut tick = interval(Duration::from_secs(2));
+    let spawn = |name| spawn_follower(ctx.clone(), name, None);
 
     loop {
+        let period = watchdog_period(&ctx);
         tokio::select! {
             _ = rx.changed() => {
                 let desired = rx.borrow().clone();
                 if desired != current {
-                    if let Some(mut ch) = child_opt.take() {
-                        let _ = ch.kill().await;
-                    }
-                    if let Some(name) = desired.clone() {
-                        match spawn_follower(ctx.clone(), name).await {
-                            Ok(child) => { child_opt = Some(child); }
-                            Err(e) => eprintln!("mpris-bridge: spawn follower failed: {e:#}"),
-                        }
-                    }
+                    replace_follower(&ctx, &mut child_opt, desired.clone(), spawn).await;
                     current = desired;
                 }
             }
-            _ = tick.tick() => {
-                // Watchdog: selected exists but follower not alive -> respawn
+            // Playback resumed while idle: re-arm at the fast period
+            () = ctx.idle_wake.notified() => {}
+            () = tokio::time::sleep(period) => {
+                // Watchdog: selected exists but follower not alive (or stalled) -> respawn
                 let selected = ctx.selected.read().unwrap().clone();
                 let alive = ctx.follower_alive.load(Ordering::SeqCst);
-                if selected.is_some() && !alive {
-                    if let Some(mut ch) = child_opt.take() {
-                        let _ = ch.kill().await;
-                    }
-                    if let Some(name) = selected.clone() {
-                        match spawn_follower(ctx.clone(), name).await {
-                            Ok(child) => { child_opt = Some(child); }
-                            Err(e) => eprintln!("mpris-bridge: respawn follower failed: {e:#}"),
-                        }
-                    }
+                let stalled = alive && selected.as_deref().is_some_and(|s| follower_stalled(&ctx, s));
+                if selected.is_some() && (!alive || stalled) {
+                    replace_follower(&ctx, &mut child_opt, selected.clone(), spawn).await;
                     current = selected;
                 }
             }
@@ -743,35 +2118,125 @@ async fn follower_manager(ctx: Arc<Ctx>, mut rx: watch::Receiver<Option<String>>
     }
 }
 
+// Player for an extra output: an active one matching the prefix, else the first match.
+fn pick_extra_player(ctx: &Ctx, prefix: &str) -> Option<String> {
+    let mut matching: Vec<String> =
+        ctx.players.read().unwrap().iter().filter(|p| p.starts_with(prefix)).cloned().collect();
+    matching.sort();
+    let status = ctx.status.read().unwrap();
+    let active = matching
+        .iter()
+        .find(|p| status.get(*p).is_some_and(|s| is_active_status(ctx, s)))
+        .cloned();
+    active.or_else(|| matching.into_iter().next())
+}
+
+// One per [[output.extra]]: keeps a follower on the matching player, independent of selection.
+async fn extra_follower_manager(ctx: Arc<Ctx>, sink: Arc<ExtraSink>) {
+    let mut current: Option<String> = None;
+    let mut child_opt: Option<Child> = None;
+
+    // The file exists from startup on, even before the player first shows up
+    if let Err(e) = write_extra(&ctx, &sink, &UiState::empty(&ctx.blank_thumbnail)) {
+        warn!("write_state error: {e:#}");
+    }
+
+    loop {
+        let period = watchdog_period(&ctx);
+        tokio::select! {
+            () = ctx.idle_wake.notified() => {}
+            () = tokio::time::sleep(period) => {}
+        }
+        let desired = pick_extra_player(&ctx, &sink.player);
+        let dead = desired.is_some() && !sink.alive.load(Ordering::SeqCst);
+        if desired == current && !dead {
+            continue;
+        }
+        // Same guard as replace_follower: the old reader's exit and late writes are ignored
+        sink.generation.fetch_add(1, Ordering::SeqCst);
+        sink.alive.store(false, Ordering::SeqCst);
+        if let Some(mut ch) = child_opt.take() {
+            let _ = ch.kill().await;
+        }
+        if let Some(name) = desired.clone() {
+            match spawn_follower(ctx.clone(), name, Some(sink.clone())) {
+                Ok(child) => child_opt = Some(child),
+                Err(e) => error!("spawn extra follower for {} failed: {e:#}", sink.player),
+            }
+        } else {
+            // Player gone: blank the extra output
+            let st = UiState::empty(&ctx.blank_thumbnail);
+            if let Err(e) = write_extra(&ctx, &sink, &st) {
+                warn!("write_state error: {e:#}");
+            }
+        }
+        current = desired;
+    }
+}
+
+// output.poll_position_ms: for players that never report position changes (e.g. Firefox).
+// Reads the real value rather than interpolating; only the selected player, only while playing.
+async fn position_poller(ctx: Arc<Ctx>, every: Duration) {
+    let mut tick = tokio::time::interval(every);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tick.tick().await;
+        let Some(name) = ctx.selected.read().unwrap().clone() else {
+            continue;
+        };
+        let playing = ctx.status.read().unwrap().get(&name).is_some_and(|s| is_active_status(&ctx, s));
+        if !playing {
+            continue;
+        }
+        let permit = query_permit(&ctx).await;
+        let out = playerctl(&ctx, &name)
+            .arg("position")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .await;
+        drop(permit);
+        let Some(pos) = parse_status_output(out).and_then(|s| s.parse::<f64>().ok()) else {
+            continue;
+        };
+        write_optimistic_position(&ctx, &name, |_| pos);
+    }
+}
+
 // ------------------------- Quick snapshot on selection change -------------------------
 
+// Also re-emits the current track on startup, resume and IPC changes; with output.quick_snapshot
+// off, all of these wait for the follower's next line instead.
 async fn emit_quick_snapshot(ctx: Arc<Ctx>, name: String) {
+    if !ctx.cfg.output.quick_snapshot {
+        return;
+    }
     // One-shot metadata for instant UI refresh on selection switch
-    let out = Command::new("playerctl")
-        .arg("-p")
-        .arg(&name)
+    let permit = query_permit(&ctx).await;
+    let out = playerctl(&ctx, &name)
         .arg("metadata")
         .arg("--format")
-        .arg("{{status}}|{{playerName}}|{{title}}|{{artist}}|{{mpris:length}}|{{mpris:artUrl}}|{{position}}|{{xesam:url}}")
+        .arg(METADATA_FORMAT)
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output()
         .await;
+    drop(permit);
 
     let Ok(o) = out else { return; };
     let s = String::from_utf8_lossy(&o.stdout);
-    let parts: Vec<_> = s.trim().splitn(8, '|').map(|x| x.to_string()).collect();
-    if parts.len() != 8 {
+    let parts: Vec<_> = s.trim().splitn(METADATA_FIELDS, '|').map(ToString::to_string).collect();
+    if parts.len() != METADATA_FIELDS {
         return;
     }
 
     let status = parts[0].clone();
     let title = parts[2].clone();
-    let artist = parts[3].clone();
+    let artist = metadata_artist(&ctx, &parts);
     let len_us = parts[4].clone();
     let art = parts[5].clone();
     let pos_us = parts[6].clone();
-    let url = parts[7].clone();
+    let url = parts[9].clone();
 
     {
         ctx.status
@@ -780,32 +2245,37 @@ async fn emit_quick_snapshot(ctx: Arc<Ctx>, name: String) {
             .insert(name.clone(), status.clone());
     }
 
-    let (n, p) = get_caps_dbus(&name).await;
+    let (n, p, can_control, fullscreen) = get_caps_dbus(&ctx, &name).await;
     let (n, p) = override_caps_for_youtube(&name, &url, n, p);
 
-    let mut st = UiState::empty(&ctx.default_cover.to_string_lossy());
+    let mut st = UiState::empty(&ctx.blank_thumbnail);
     st.name = name.clone();
-    st.status = status;
-    st.title = truncate(&title, ctx.cfg.presentation.truncate_title);
-    st.artist = truncate(&artist, ctx.cfg.presentation.truncate_artist);
+    st.bus_name = mpris_bus_name(&name);
+    st.status = normalize_status(&ctx, &status);
+    st.status_icon = status_icon(&ctx.cfg.presentation, &name, &st.status);
+    st.status_raw = status;
+    (st.title, st.artist) = truncate_title_artist(&ctx, &title, &artist);
+    st.track_hash = track_hash(&title, &artist, &parts[7], &len_us);
 
     if let Ok(us) = len_us.parse::<u64>() {
-        st.length = (us as f64) / 1_000_000.0;
+        st.length = Duration::from_micros(us).as_secs_f64();
         st.length_str = fmt_time(st.length);
     }
     if let Ok(usf) = pos_us.parse::<f64>() {
         let pos = usf / 1_000_000.0;
         st.position = pos;
         st.position_str = fmt_time(pos);
+        st.position_ts = epoch_secs();
     }
 
-    st.thumbnail = update_art(&ctx, &art)
-        .await
-        .unwrap_or_else(|_| ctx.default_cover.to_string_lossy().to_string());
+    (st.thumbnail, (st.art_width, st.art_height)) = main_thumbnail(&ctx, &name, &art).await;
     st.can_next = n;
     st.can_prev = p;
+    st.can_control = can_control;
+    st.fullscreen = fullscreen;
+    st.source_kind = source_kind(&url).into();
 
-    let _ = write_state(&ctx, &st).await;
+    let _ = write_state(&ctx, &st);
 }
 
 // ------------------------- IPC (Unix socket) -------------------------
@@ -822,19 +2292,85 @@ enum IpcCmd {
     Previous { player: Option<String> },
     #[serde(rename = "seek")]
     Seek { offset: f64, player: Option<String> }, // seconds (+/-)
+    #[serde(rename = "seek-step")]
+    SeekStep { forward: bool, player: Option<String> }, // ± selection.seek_step_secs
+    #[serde(rename = "smart-previous")]
+    SmartPrevious { threshold_secs: Option<f64>, player: Option<String> }, // restart if past threshold (default 3s), else previous
     #[serde(rename = "set-position")]
     SetPosition { position: f64, player: Option<String> }, // seconds (absolute)
+    #[serde(rename = "pin")]
+    PinPriority { player: String, ttl_secs: u64 }, // prefix boosted above `priority` for ttl_secs
+    #[serde(rename = "toggle-players")]
+    TogglePlayers { a: String, b: String }, // select the other of two prefixes, locked for TOGGLE_LOCK_SECS
+    #[serde(rename = "clear-last")]
+    ClearLast, // forget the remember_last player
+    #[serde(rename = "ping")]
+    Ping, // liveness probe; touches no player
+    #[serde(rename = "playlists")]
+    Playlists { player: Option<String> },
+    #[serde(rename = "activate-playlist")]
+    ActivatePlaylist { id: String, player: Option<String> }, // id = playlist object path
+    #[serde(rename = "set-loop")]
+    SetLoop { mode: String, player: Option<String> }, // "none" | "track" | "playlist"
+    #[serde(rename = "set-shuffle")]
+    SetShuffle { on: bool, player: Option<String> },
+    #[serde(rename = "caps")]
+    Caps { player: Option<String> }, // MPRIS capability flags, read live
+    #[serde(rename = "toggle-fullscreen")]
+    ToggleFullscreen { player: Option<String> }, // needs CanSetFullscreen
+    #[serde(rename = "meta")]
+    Meta { player: Option<String> }, // full metadata map (all xesam:/mpris: keys playerctl exposes)
+    #[serde(rename = "subscribe")]
+    Subscribe {
+        #[serde(default)]
+        framing: Framing,
+    }, // turns the connection into an event stream
+    #[serde(rename = "freeze")]
+    Freeze { on: bool }, // hold all output writes (demo mode)
+    #[serde(rename = "set-truncation")]
+    SetTruncation { title: Option<usize>, artist: Option<usize> }, // runtime override of presentation.truncate_*
+    #[serde(rename = "recent-events")]
+    RecentEvents { n: usize }, // last n states from output.events_memory_ring
+    #[serde(rename = "get-config")]
+    GetConfig, // effective config, paths expanded
+    #[serde(rename = "resolve-identity")]
+    ResolveIdentity { identity: String }, // MPRIS Identity (e.g. "Mozilla Firefox") -> player name
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Framing {
+    #[default]
+    Newline, // one JSON object per line
+    LengthPrefixed, // 4-byte big-endian length, then the JSON bytes
 }
 
-fn pick_player_sync(ctx: &Ctx, explicit: &Option<String>) -> Option<String> {
-    if let Some(p) = explicit.clone() {
-        return Some(p);
+const TOGGLE_LOCK_SECS: u64 = 10;
+const SMART_PREVIOUS_SECS: f64 = 3.0; // smart-previous restarts the track past this position
+
+// The other of `a`/`b` than the selected player (A when neither is selected); if that one
+// isn't running, whichever of the two is.
+fn toggle_target(ctx: &Ctx, a: &str, b: &str) -> Result<String> {
+    let selected = ctx.selected.read().unwrap().clone().unwrap_or_default();
+    let (want, other) = if !a.is_empty() && selected.starts_with(a) { (b, a) } else { (a, b) };
+    let mut sorted: Vec<String> = ctx.players.read().unwrap().iter().cloned().collect();
+    sorted.sort();
+    let find = |prefix: &str| sorted.iter().find(|p| p.starts_with(prefix)).cloned();
+    find(want)
+        .or_else(|| find(other))
+        .with_context(|| format!("neither {a} nor {b} is running"))
+}
+
+fn pick_player_sync(ctx: &Ctx, explicit: Option<&String>) -> Option<String> {
+    if let Some(p) = explicit {
+        return Some(p.clone());
     }
     ctx.selected.read().unwrap().clone()
 }
 
-fn run_playerctl_cmd_sync(player: &str, args: &[&str]) {
+fn run_playerctl_cmd_sync(ctx: &Ctx, player: &str, args: &[&str]) {
     let _ = std::process::Command::new("playerctl")
+        .args(player_args(ctx, player))
         .arg("-p")
         .arg(player)
         .args(args)
@@ -843,8 +2379,385 @@ fn run_playerctl_cmd_sync(player: &str, args: &[&str]) {
         .status();
 }
 
-fn handle_ipc_stream_blocking(ctx: Arc<Ctx>, mut stream: UnixStream) {
-    use std::io::{BufRead, BufReader, Write};
+fn interpolated_position(st: &UiState, now: f64) -> f64 {
+    if st.status == "playing" {
+        st.position + (now - st.position_ts).max(0.0)
+    } else {
+        st.position
+    }
+}
+
+// After a seek, publish the intended position right away; the follower corrects it later.
+// Also used by the position poller. Only for the selected player, so a stale cached state never overwrites another player's.
+fn write_optimistic_position(ctx: &Ctx, player: &str, new_pos: impl FnOnce(f64) -> f64) {
+    if ctx.selected.read().unwrap().as_deref() != Some(player) {
+        return;
+    }
+    let cached = ctx.last_state.read().unwrap().clone();
+    let Some(mut st) = cached.filter(|st| st.name == player) else {
+        return;
+    };
+    let now = epoch_secs();
+    let current = interpolated_position(&st, now);
+    let mut pos = new_pos(current).max(0.0);
+    if st.length > 0.0 {
+        pos = pos.min(st.length);
+    }
+    st.event = "position";
+    st.position = pos;
+    st.position_str = fmt_time(pos);
+    st.position_ts = now;
+    if let Err(e) = write_state(ctx, &st) {
+        warn!("write_state error: {e:#}");
+    }
+}
+
+fn seek_relative_sync(ctx: &Ctx, player: &str, offset: f64) {
+    // playerctl position takes "5+" or "5-"
+    let offset = offset.trunc();
+    let s = format!("{}{}", offset.abs(), if offset >= 0.0 { '+' } else { '-' });
+    run_playerctl_cmd_sync(ctx, player, &["position", &s]);
+    write_optimistic_position(ctx, player, |cur| cur + offset);
+}
+
+// Daemon-side D-Bus calls for IPC commands that playerctl can't express.
+fn mpris_proxy<'a>(
+    conn: &'a zbus::blocking::Connection,
+    simple_name: &str,
+    iface: &'static str,
+) -> Result<zbus::blocking::Proxy<'a>> {
+    let busname = mpris_bus_name(simple_name);
+    Ok(zbus::blocking::Proxy::new(conn, busname, "/org/mpris/MediaPlayer2", iface)?)
+}
+
+// The followed player's CanControl is already in the last state (refreshed on track/status
+// changes), which spares each keypress a D-Bus round-trip; other players are asked live.
+// Unreadable CanControl counts as controllable.
+fn can_control_sync(ctx: &Ctx, simple_name: &str) -> bool {
+    let cached = ctx.last_state.read().unwrap().as_ref().filter(|st| st.name == simple_name).map(|st| st.can_control);
+    if let Some(can_control) = cached {
+        return can_control != 0;
+    }
+    let Ok(conn) = zbus::blocking::Connection::session() else {
+        return true;
+    };
+    mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2.Player")
+        .and_then(|p| Ok(p.get_property::<bool>("CanControl")?))
+        .unwrap_or(true)
+}
+
+// Players that don't report CanSeek are assumed seekable.
+fn can_seek_sync(simple_name: &str) -> bool {
+    let Ok(conn) = zbus::blocking::Connection::session() else {
+        return true;
+    };
+    mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2.Player")
+        .and_then(|p| Ok(p.get_property::<bool>("CanSeek")?))
+        .unwrap_or(true)
+}
+
+// Unreadable CanSeek/CanControl count as true (as for control), the rest as false. A player
+// that doesn't answer at all is an error. `controllable` also honors selection.control_blacklist.
+fn caps_sync(ctx: &Ctx, simple_name: &str) -> Result<serde_json::Value> {
+    let conn = zbus::blocking::Connection::session()?;
+    let proxy = mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2.Player")?;
+    let can_control = proxy
+        .get_property::<bool>("CanControl")
+        .or_else(|_| proxy.get_property::<String>("PlaybackStatus").map(|_| true))
+        .with_context(|| format!("player {simple_name} not found"))?;
+    let flag = |prop: &str, default: bool| proxy.get_property::<bool>(prop).unwrap_or(default);
+    let blacklisted = ctx.cfg.selection.control_blacklist.iter().any(|b| simple_name.starts_with(b.as_str()));
+    Ok(serde_json::json!({
+        "player": simple_name,
+        "can_next": flag("CanGoNext", false),
+        "can_prev": flag("CanGoPrevious", false),
+        "can_seek": flag("CanSeek", true),
+        "can_control": can_control,
+        "can_pause": flag("CanPause", false),
+        "can_play": flag("CanPlay", false),
+        "controllable": can_control && !blacklisted,
+    }))
+}
+
+// Flips the root Fullscreen property; returns the new value.
+fn toggle_fullscreen_sync(simple_name: &str) -> Result<bool> {
+    let conn = zbus::blocking::Connection::session()?;
+    let root = mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2")?;
+    anyhow::ensure!(
+        root.get_property::<bool>("CanSetFullscreen").unwrap_or(false),
+        "{simple_name} can't set fullscreen (CanSetFullscreen is false or missing)"
+    );
+    let on = !root.get_property::<bool>("Fullscreen").unwrap_or(false);
+    root.set_property("Fullscreen", on)?;
+    Ok(on)
+}
+
+type PlaylistEntry = (OwnedObjectPath, String, String); // (id, name, icon)
+
+fn playlist_json((id, name, icon): &PlaylistEntry) -> serde_json::Value {
+    serde_json::json!({"id": id.as_str(), "name": name, "icon": icon})
+}
+
+fn get_playlists_sync(simple_name: &str) -> Result<serde_json::Value> {
+    let conn = zbus::blocking::Connection::session().context("dbus session")?;
+    let proxy = mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2.Playlists")?;
+    let lists: Vec<PlaylistEntry> = proxy
+        .call("GetPlaylists", &(0u32, 1000u32, "Alphabetical", false))
+        .with_context(|| format!("{simple_name} does not support org.mpris.MediaPlayer2.Playlists"))?;
+    // ActivePlaylist is (valid, playlist); invalid means "none active".
+    let active = proxy
+        .get_property::<(bool, PlaylistEntry)>("ActivePlaylist")
+        .ok()
+        .filter(|(valid, _)| *valid)
+        .map(|(_, pl)| playlist_json(&pl));
+    Ok(serde_json::json!({
+        "playlists": lists.iter().map(playlist_json).collect::<Vec<_>>(),
+        "active": active,
+    }))
+}
+
+fn activate_playlist_sync(simple_name: &str, id: &str) -> Result<()> {
+    let path = ObjectPath::try_from(id).with_context(|| format!("invalid playlist id {id:?}"))?;
+    let conn = zbus::blocking::Connection::session().context("dbus session")?;
+    let proxy = mpris_proxy(&conn, simple_name, "org.mpris.MediaPlayer2.Playlists")?;
+    proxy
+        .call::<_, _, ()>("ActivatePlaylist", &(path,))
+        .with_context(|| format!("{simple_name} does not support org.mpris.MediaPlayer2.Playlists"))?;
+    Ok(())
+}
+
+// `playerctl metadata` prints "<player> <key> <value>" per line; list values come comma-joined.
+fn get_metadata_sync(ctx: &Ctx, simple_name: &str) -> Result<serde_json::Value> {
+    let out = std::process::Command::new("playerctl")
+        .args(player_args(ctx, simple_name))
+        .arg("-p")
+        .arg(simple_name)
+        .arg("metadata")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .context("playerctl metadata")?;
+    if !out.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let mut map = serde_json::Map::new();
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        let mut it = line.splitn(3, char::is_whitespace);
+        let (Some(_player), Some(key)) = (it.next(), it.next()) else {
+            continue;
+        };
+        let value = it.next().unwrap_or("").trim();
+        map.insert(key.to_string(), value.into());
+    }
+    Ok(serde_json::json!({"player": simple_name, "metadata": map}))
+}
+
+// The running config with every path field replaced by the expanded path actually in use.
+fn effective_config_json(ctx: &Ctx) -> Result<serde_json::Value> {
+    let mut v = serde_json::to_value(&ctx.cfg)?;
+    let path = |p: &Path| serde_json::Value::from(p.to_string_lossy().to_string());
+    let art = &mut v["art"];
+    art["cache_dir"] = ctx.cfg.art.cache_dir.as_deref().map(expand).into();
+    art["http_cache_dir"] = path(&ctx.http_cache_dir);
+    art["embedded_cache_dir"] = ctx.embedded_cache_dir.as_deref().map(path).into();
+    art["default_image"] = path(&ctx.default_cover);
+    art["error_image"] = path(&ctx.error_cover);
+    art["loading_image"] = ctx.loading_cover.as_deref().map(path).into();
+    art["current_path"] = path(&ctx.current_cover);
+    art["per_player_current_dir"] = ctx.per_player_art_dir.as_deref().map(path).into();
+    art["blank_thumbnail"] = ctx.blank_thumbnail.clone().into();
+    let output = &mut v["output"];
+    output["snapshot_path"] = path(&ctx.snapshot_path);
+    output["events_path"] = path(&ctx.events_path);
+    output["extra"] = ctx
+        .extra_outputs
+        .iter()
+        .map(|e| {
+            serde_json::json!({
+                "player": e.player,
+                "snapshot_path": path(&e.snapshot_path),
+                "events_path": path(&e.events_path),
+            })
+        })
+        .collect();
+    Ok(v)
+}
+
+// First known player whose root `Identity` property matches (case-insensitive).
+fn resolve_identity_sync(ctx: &Ctx, identity: &str) -> Result<String> {
+    let conn = zbus::blocking::Connection::session().context("dbus session")?;
+    let mut players: Vec<String> = ctx.players.read().unwrap().iter().cloned().collect();
+    players.sort();
+    for p in players {
+        let Ok(proxy) = mpris_proxy(&conn, &p, "org.mpris.MediaPlayer2") else {
+            continue;
+        };
+        if let Ok(id) = proxy.get_property::<String>("Identity") {
+            if id.eq_ignore_ascii_case(identity) {
+                return Ok(p);
+            }
+        }
+    }
+    anyhow::bail!("no player with identity {identity:?}")
+}
+
+// Execute one IPC command; the returned object is merged into the {"ok":true} reply.
+fn dispatch_ipc(ctx: &Arc<Ctx>, cmd: IpcCmd) -> Result<serde_json::Value> {
+    let pick = |player: Option<String>| pick_player_sync(ctx, player.as_ref()).context("no player selected");
+    // Commands that drive the player (not queries)
+    let control = |player: Option<String>| {
+        let p = pick(player)?;
+        if ctx.cfg.selection.control_blacklist.iter().any(|b| p.starts_with(b)) {
+            anyhow::bail!("player is control-blacklisted");
+        }
+        if !can_control_sync(ctx, &p) {
+            anyhow::bail!("{p} does not accept control (CanControl=false)");
+        }
+        Ok::<_, anyhow::Error>(p)
+    };
+    match cmd {
+        IpcCmd::Ping => return Ok(serde_json::json!({"pong": true})),
+        IpcCmd::PlayPause { player } => {
+            run_playerctl_cmd_sync(ctx, &control(player)?, &["play-pause"]);
+        }
+        IpcCmd::Next { player } => {
+            run_playerctl_cmd_sync(ctx, &control(player)?, &["next"]);
+        }
+        IpcCmd::Previous { player } => {
+            run_playerctl_cmd_sync(ctx, &control(player)?, &["previous"]);
+        }
+        IpcCmd::Seek { offset, player } => {
+            seek_relative_sync(ctx, &control(player)?, offset);
+        }
+        IpcCmd::SeekStep { forward, player } => {
+            let p = control(player)?;
+            if !can_seek_sync(&p) {
+                anyhow::bail!("{p} cannot seek");
+            }
+            let step = Duration::from_secs(ctx.cfg.selection.seek_step_secs).as_secs_f64();
+            seek_relative_sync(ctx, &p, if forward { step } else { -step });
+        }
+        IpcCmd::SmartPrevious { threshold_secs, player } => {
+            let p = control(player)?;
+            let cached = ctx.last_state.read().unwrap().clone();
+            let position = cached.filter(|st| st.name == p).map(|st| interpolated_position(&st, epoch_secs()));
+            if position.is_some_and(|pos| pos > threshold_secs.unwrap_or(SMART_PREVIOUS_SECS)) {
+                run_playerctl_cmd_sync(ctx, &p, &["position", "0"]);
+                write_optimistic_position(ctx, &p, |_| 0.0);
+                return Ok(serde_json::json!({"action": "restart"}));
+            }
+            run_playerctl_cmd_sync(ctx, &p, &["previous"]);
+            return Ok(serde_json::json!({"action": "previous"}));
+        }
+        IpcCmd::SetPosition { position, player } => {
+            let p = control(player)?;
+            let position = position.max(0.0).trunc();
+            run_playerctl_cmd_sync(ctx, &p, &["position", &position.to_string()]);
+            write_optimistic_position(ctx, &p, |_| position);
+        }
+        IpcCmd::PinPriority { player, ttl_secs } => {
+            let expiry = Instant::now() + Duration::from_secs(ttl_secs);
+            *ctx.pinned.write().unwrap() = Some((player, expiry));
+            let new_sel = recompute_selected(ctx);
+            set_selected_and_kick(ctx, new_sel);
+        }
+        IpcCmd::TogglePlayers { a, b } => {
+            let target = toggle_target(ctx, &a, &b)?;
+            let expiry = Instant::now() + Duration::from_secs(TOGGLE_LOCK_SECS);
+            *ctx.selection_lock.write().unwrap() = Some((target.clone(), expiry));
+            set_selected_and_kick(ctx, Some((target.clone(), "locked")));
+            return Ok(serde_json::json!({"player": target}));
+        }
+        IpcCmd::ClearLast => {
+            *ctx.last_selected.write().unwrap() = None;
+            let new_sel = recompute_selected(ctx);
+            let name = new_sel.as_ref().map(|(n, _)| n.clone());
+            set_selected_sync(ctx, new_sel);
+            // Re-emit even if unchanged: selectionReason may have been "remember_last"
+            if let Some(name) = name {
+                let ctx2 = ctx.clone();
+                task::spawn(async move { emit_quick_snapshot(ctx2, name).await; });
+            }
+        }
+        IpcCmd::Playlists { player } => {
+            return get_playlists_sync(&pick(player)?);
+        }
+        IpcCmd::ActivatePlaylist { id, player } => {
+            activate_playlist_sync(&control(player)?, &id)?;
+        }
+        IpcCmd::SetLoop { mode, player } => {
+            let status = mpris_bridge::loop_status(&mode)
+                .with_context(|| format!("invalid loop mode {mode:?} (expected none|track|playlist)"))?;
+            run_playerctl_cmd_sync(ctx, &control(player)?, &["loop", status]);
+        }
+        IpcCmd::SetShuffle { on, player } => {
+            run_playerctl_cmd_sync(ctx, &control(player)?, &["shuffle", if on { "On" } else { "Off" }]);
+        }
+        IpcCmd::Caps { player } => {
+            return caps_sync(ctx, &pick(player)?);
+        }
+        IpcCmd::ToggleFullscreen { player } => {
+            let p = control(player)?;
+            let on = toggle_fullscreen_sync(&p)?;
+            let ctx2 = ctx.clone();
+            task::spawn(async move { emit_quick_snapshot(ctx2, p).await; });
+            return Ok(serde_json::json!({"fullscreen": on}));
+        }
+        IpcCmd::Meta { player } => {
+            return get_metadata_sync(ctx, &pick(player)?);
+        }
+        IpcCmd::Subscribe { .. } => {
+            anyhow::bail!("subscribe is handled by the connection");
+        }
+        IpcCmd::Freeze { on } => {
+            set_frozen(ctx, on)?;
+        }
+        IpcCmd::SetTruncation { title, artist } => {
+            let (max_title, max_artist) = {
+                let mut limits = ctx.truncation.write().unwrap();
+                limits.0 = title.unwrap_or(limits.0);
+                limits.1 = artist.unwrap_or(limits.1);
+                *limits
+            };
+            // Re-emit the current track with the new limits
+            let selected = ctx.selected.read().unwrap().clone();
+            if let Some(name) = selected {
+                let ctx2 = ctx.clone();
+                task::spawn(async move { emit_quick_snapshot(ctx2, name).await; });
+            }
+            return Ok(serde_json::json!({"title": max_title, "artist": max_artist}));
+        }
+        IpcCmd::RecentEvents { n } => {
+            anyhow::ensure!(events_ring_cap(ctx).is_some(), "output.events_memory_ring is not enabled");
+            return Ok(serde_json::json!({"events": recent_events(ctx, n)?}));
+        }
+        IpcCmd::GetConfig => {
+            return Ok(serde_json::json!({"config": effective_config_json(ctx)?}));
+        }
+        IpcCmd::ResolveIdentity { identity } => {
+            return Ok(serde_json::json!({"player": resolve_identity_sync(ctx, &identity)?}));
+        }
+    }
+    Ok(serde_json::json!({}))
+}
+
+// A connection the IPC protocol runs over: the Unix socket, or output.ipc_tcp_listen.
+trait IpcStream: std::io::Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+}
+impl IpcStream for UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        Self::try_clone(self)
+    }
+}
+impl IpcStream for TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        Self::try_clone(self)
+    }
+}
+
+fn handle_ipc_stream_blocking<S: IpcStream>(ctx: &Arc<Ctx>, mut stream: S) {
+    use std::io::{BufRead, BufReader};
     let mut reader = BufReader::new(stream.try_clone().unwrap());
     let mut line = String::new();
 
@@ -858,107 +2771,166 @@ fn handle_ipc_stream_blocking(ctx: Arc<Ctx>, mut stream: UnixStream) {
         if txt.is_empty() {
             continue;
         }
-        let mut ok = true;
-        if let Ok(cmd) = serde_json::from_str::<IpcCmd>(txt) {
-            match cmd {
-                IpcCmd::PlayPause { player } => {
-                    if let Some(p) = pick_player_sync(&ctx, &player) {
-                        run_playerctl_cmd_sync(&p, &["play-pause"]);
-                    } else {
-                        ok = false;
-                    }
-                }
-                IpcCmd::Next { player } => {
-                    if let Some(p) = pick_player_sync(&ctx, &player) {
-                        run_playerctl_cmd_sync(&p, &["next"]);
-                    } else {
-                        ok = false;
-                    }
-                }
-                IpcCmd::Previous { player } => {
-                    if let Some(p) = pick_player_sync(&ctx, &player) {
-                        run_playerctl_cmd_sync(&p, &["previous"]);
-                    } else {
-                        ok = false;
-                    }
-                }
-                IpcCmd::Seek { offset, player } => {
-                    if let Some(p) = pick_player_sync(&ctx, &player) {
-                        // playerctl position takes "5+" or "5-"
-                        let s = if offset >= 0.0 {
-                            format!("{}+", offset as i64)
-                        } else {
-                            format!("{}-", (-offset) as i64)
-                        };
-                        run_playerctl_cmd_sync(&p, &["position", &s]);
-                    } else {
-                        ok = false;
-                    }
-                }
-                IpcCmd::SetPosition { position, player } => {
-                    if let Some(p) = pick_player_sync(&ctx, &player) {
-                        let s = format!("{}", position as i64);
-                        run_playerctl_cmd_sync(&p, &["position", &s]);
-                    } else {
-                        ok = false;
-                    }
+        let cmd = serde_json::from_str::<IpcCmd>(txt).context("invalid command");
+        if let Ok(IpcCmd::Subscribe { framing }) = cmd {
+            stream_events_blocking(ctx, stream, framing);
+            return;
+        }
+        let res = cmd.and_then(|cmd| dispatch_ipc(ctx, cmd));
+        let reply = match res {
+            Ok(mut v) => {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.insert("ok".into(), true.into());
                 }
+                v
             }
-        } else {
-            ok = false;
-        }
-
-        let _ = if ok {
-            write!(stream, "{{\"ok\":true}}\n")
-        } else {
-            write!(stream, "{{\"ok\":false}}\n")
+            Err(e) => serde_json::json!({"ok": false, "error": format!("{e:#}")}),
         };
+
+        let _ = writeln!(stream, "{reply}");
         let _ = stream.flush();
     }
 }
 
-fn ipc_server_blocking(ctx: Arc<Ctx>) -> std::io::Result<()> {
-    // $XDG_RUNTIME_DIR/mpris-bridge/mpris-bridge.sock
-    let base = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/run/user/1000".into());
-    let dir = format!("{base}/mpris-bridge");
-    let sock = format!("{dir}/mpris-bridge.sock");
-    let _ = fs::create_dir_all(&dir);
+fn write_frame(stream: &mut impl Write, framing: Framing, json: &str) -> std::io::Result<()> {
+    match framing {
+        Framing::Newline => writeln!(stream, "{json}")?,
+        Framing::LengthPrefixed => {
+            let len = u32::try_from(json.len()).map_err(std::io::Error::other)?;
+            stream.write_all(&len.to_be_bytes())?;
+            stream.write_all(json.as_bytes())?;
+        }
+    }
+    stream.flush()
+}
+
+// Subscriber: the current state first, then every state as it is written, until the peer hangs up.
+fn stream_events_blocking(ctx: &Ctx, mut stream: impl Write, framing: Framing) {
+    let mut rx = ctx.events_tx.subscribe();
+    let current = ctx.last_state.read().unwrap().clone();
+    if let Some(st) = current {
+        let Ok(json) = state_json(ctx, &st, false) else { return; };
+        if write_frame(&mut stream, framing, &json).is_err() {
+            return;
+        }
+    }
+    loop {
+        match rx.blocking_recv() {
+            Ok(json) => {
+                if write_frame(&mut stream, framing, &json).is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => debug!("subscriber lagged, {n} events dropped"),
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+fn ipc_server_blocking(ctx: &Arc<Ctx>) -> std::io::Result<()> {
+    // $XDG_RUNTIME_DIR/mpris-bridge[-<instance>]/mpris-bridge.sock
+    let sock = ctx.socket_path.clone();
+    if let Some(dir) = sock.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
     let _ = fs::remove_file(&sock);
     let listener = UnixListener::bind(&sock)?;
     let _ = fs::set_permissions(&sock, fs::Permissions::from_mode(0o600));
+    serve_ipc_blocking(ctx, listener.incoming());
+    Ok(())
+}
 
-    for conn in listener.incoming() {
+// output.ipc_tcp_listen: same protocol over TCP. No authentication, so keep it on loopback
+// (or behind an SSH tunnel) unless the network is trusted.
+fn ipc_tcp_server_blocking(ctx: &Arc<Ctx>, addr: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    if !local.ip().is_loopback() {
+        warn!("IPC listening on non-loopback {local}: anyone who can reach it can control playback");
+    }
+    info!("IPC also listening on tcp {local}");
+    serve_ipc_blocking(ctx, listener.incoming());
+    Ok(())
+}
+
+fn serve_ipc_blocking<S: IpcStream>(ctx: &Arc<Ctx>, incoming: impl Iterator<Item = std::io::Result<S>>) {
+    // Handlers run on plain threads; keep the runtime reachable for task::spawn.
+    let rt = tokio::runtime::Handle::current();
+    for conn in incoming {
         match conn {
             Ok(stream) => {
                 let ctx2 = ctx.clone();
+                let rt2 = rt.clone();
                 std::thread::spawn(move || {
-                    handle_ipc_stream_blocking(ctx2, stream);
+                    let _guard = rt2.enter();
+                    handle_ipc_stream_blocking(&ctx2, stream);
                 });
             }
             Err(e) => {
-                eprintln!("mpris-bridge: ipc accept error: {e:#}");
+                warn!("ipc accept error: {e:#}");
             }
         }
     }
-    Ok(())
 }
 
 // ------------------------- D-Bus (zbus) + Hypr focus -------------------------
 
+// Marker for clients: state may be stale until the next update after resubscribing.
+fn emit_reconnect_event(ctx: &Ctx) {
+    let last = ctx.last_state.read().unwrap().clone();
+    let mut st = last.unwrap_or_else(|| UiState::empty(&ctx.blank_thumbnail));
+    st.event = "reconnect";
+    if let Err(e) = write_state(ctx, &st) {
+        warn!("write_state error: {e:#}");
+    }
+}
+
 // Reconnecting wrapper with backoff
+// Falls back to polling playerctl when the session bus stays unreachable.
 async fn dbus_listener(ctx: Arc<Ctx>) -> Result<()> {
     use tokio::time::sleep;
     let mut backoff_secs: u64 = 1;
+    let mut connect_failures: u32 = 0;
+
+    loop {
+        let conn = match Connection::session().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                connect_failures = connect_failures.saturating_add(1);
+                if connect_failures < DBUS_FAILURES_BEFORE_POLL {
+                    warn!("dbus session error: {e:#} (will reconnect)");
+                    let delay = (backoff_secs.min(30)) * 200;
+                    sleep(Duration::from_millis(delay)).await;
+                    backoff_secs = (backoff_secs.saturating_mul(2)).min(30);
+                    continue;
+                }
+                if connect_failures == DBUS_FAILURES_BEFORE_POLL {
+                    warn!("session bus unavailable ({e:#}); degraded polling mode via playerctl");
+                }
+                if let Err(e) = seed_players(&ctx).await {
+                    warn!("poll seed failed: {e:#}");
+                }
+                let new_sel = recompute_selected(&ctx);
+                set_selected_and_kick(&ctx, new_sel);
+                sleep(Duration::from_millis(DEGRADED_POLL_MS)).await;
+                continue;
+            }
+        };
+        if connect_failures >= DBUS_FAILURES_BEFORE_POLL {
+            info!("session bus available again; back to event-driven mode");
+        }
+        connect_failures = 0;
 
-    loop {
-        match dbus_main_loop(ctx.clone()).await {
+        let res = dbus_main_loop(ctx.clone(), conn).await;
+        emit_reconnect_event(&ctx);
+        match res {
             Ok(()) => {
                 // Graceful end, small delay and restart
                 sleep(Duration::from_millis(500)).await;
                 backoff_secs = 1;
             }
             Err(e) => {
-                eprintln!("mpris-bridge: dbus loop error: {e:#} (will reconnect)");
+                warn!("dbus loop error: {e:#} (will reconnect)");
                 let delay = (backoff_secs.min(30)) * 200;
                 sleep(Duration::from_millis(delay)).await;
                 backoff_secs = (backoff_secs.saturating_mul(2)).min(30);
@@ -967,21 +2939,44 @@ async fn dbus_listener(ctx: Arc<Ctx>) -> Result<()> {
     }
 }
 
-// Single DBus session: connect, subscribe and process
-async fn dbus_main_loop(ctx: Arc<Ctx>) -> Result<()> {
-    let conn = Connection::session().await.context("dbus session")?;
+const SEED_DEBOUNCE_MS: u64 = 300;
+const REFRESH_DEBOUNCE_MS: u64 = 250;
+const DBUS_FAILURES_BEFORE_POLL: u32 = 5;
+const DEGRADED_POLL_MS: u64 = 2000;
+
+// --trace-dbus: the interesting part of the signals we subscribe to, else the body signature.
+fn dbus_body_summary(msg: &zbus::Message) -> String {
+    if let Ok((name, old, new)) = msg.body::<(String, String, String)>() {
+        return format!("name={name} old_owner={old:?} new_owner={new:?}");
+    }
+    if let Ok((iface, changed, invalidated)) = msg.body::<(String, HashMap<String, OwnedValue>, Vec<String>)>() {
+        let mut keys: Vec<&String> = changed.keys().collect();
+        keys.sort();
+        return format!("iface={iface} changed={keys:?} invalidated={invalidated:?}");
+    }
+    msg.body_signature().map_or_else(|_| "?".into(), |sig| format!("signature={sig}"))
+}
+
+// Single DBus session: subscribe and process
+async fn dbus_main_loop(ctx: Arc<Ctx>, conn: Connection) -> Result<()> {
 
     // Сузить подписки: только MPRIS-плееры и их свойства на стандартном пути.
     let dbus = DBusProxy::new(&conn).await?;
     // Смена владельцев ТОЛЬКО для имён в пространстве org.mpris.MediaPlayer2.*
-    dbus.add_match("type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0namespace='org.mpris.MediaPlayer2'")
-        .await?;
+    dbus.add_match_rule(MatchRule::try_from(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0namespace='org.mpris.MediaPlayer2'",
+    )?)
+    .await?;
     // Изменения свойств ТОЛЬКО на /org/mpris/MediaPlayer2 для интерфейса Player
-    dbus.add_match("type='signal',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='/org/mpris/MediaPlayer2',arg0='org.mpris.MediaPlayer2.Player'")
-        .await?;
+    dbus.add_match_rule(MatchRule::try_from(
+        "type='signal',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='/org/mpris/MediaPlayer2',arg0='org.mpris.MediaPlayer2.Player'",
+    )?)
+    .await?;
     // И (реже) для корневого интерфейса org.mpris.MediaPlayer2 (необязательно, но полезно)
-    dbus.add_match("type='signal',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='/org/mpris/MediaPlayer2',arg0='org.mpris.MediaPlayer2'")
-        .await?;
+    dbus.add_match_rule(MatchRule::try_from(
+        "type='signal',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='/org/mpris/MediaPlayer2',arg0='org.mpris.MediaPlayer2'",
+    )?)
+    .await?;
 
     let mut stream = MessageStream::from(&conn);
 
@@ -991,10 +2986,12 @@ async fn dbus_main_loop(ctx: Arc<Ctx>) -> Result<()> {
     set_selected_and_kick(&ctx, init_sel);
 
     // Дебаунс тяжёлых операций, выполняем в фоновых задачах
-    let mut last_seed = Instant::now() - Duration::from_secs(3600);
-    let mut last_refresh = Instant::now() - Duration::from_secs(3600);
-    const SEED_DEBOUNCE_MS: u64 = 300;
-    const REFRESH_DEBOUNCE_MS: u64 = 250;
+    // Duration::from_hours needs a newer toolchain than we support; older clippies don't know
+    // the lint that suggests it
+    #[allow(unknown_lints, clippy::duration_suboptimal_units)]
+    let long_ago = Instant::now() - Duration::from_secs(3600);
+    let mut last_seed = long_ago;
+    let mut last_refresh = long_ago;
 
     // React to bus signals
     while let Some(msg) = stream.next().await {
@@ -1007,34 +3004,77 @@ async fn dbus_main_loop(ctx: Arc<Ctx>) -> Result<()> {
         let iface = hdr.interface().ok().flatten().map(|i| i.as_str().to_string());
         let member = hdr.member().ok().flatten().map(|m| m.as_str().to_string());
         let path = hdr.path().ok().flatten().map(|p| p.as_str().to_string());
+        if ctx.cfg.logging.trace_dbus {
+            let sender = hdr.sender().ok().flatten().map(ToString::to_string);
+            debug!(
+                "dbus signal {}.{} path={} sender={} body={}",
+                iface.as_deref().unwrap_or("?"),
+                member.as_deref().unwrap_or("?"),
+                path.as_deref().unwrap_or("?"),
+                sender.as_deref().unwrap_or("?"),
+                dbus_body_summary(&msg)
+            );
+        }
 
         match (iface.as_deref(), member.as_deref()) {
-            (Some("org.freedesktop.DBus"), Some("NameOwnerChanged")) => {
-                // Уже отфильтровано по arg0namespace='org.mpris.MediaPlayer2'
-                if last_seed.elapsed() >= Duration::from_millis(SEED_DEBOUNCE_MS) {
-                    last_seed = Instant::now();
-                    let ctx2 = ctx.clone();
-                    task::spawn(async move {
-                        if let Err(e) = seed_players(&ctx2).await {
-                            eprintln!("mpris-bridge: seed on NameOwnerChanged failed: {e:#}");
-                            return;
-                        }
+            // Уже отфильтровано по arg0namespace='org.mpris.MediaPlayer2'
+            (Some("org.freedesktop.DBus"), Some("NameOwnerChanged"))
+                if last_seed.elapsed() >= Duration::from_millis(SEED_DEBOUNCE_MS) =>
+            {
+                last_seed = Instant::now();
+                let ctx2 = ctx.clone();
+                task::spawn(async move {
+                    let grace = Duration::from_millis(ctx2.cfg.selection.player_exit_grace_ms);
+                    loop {
+                        let lingering = match seed_players(&ctx2).await {
+                            Ok(l) => l,
+                            Err(e) => {
+                                warn!("seed on NameOwnerChanged failed: {e:#}");
+                                return;
+                            }
+                        };
                         let new_sel = recompute_selected(&ctx2);
                         set_selected_and_kick(&ctx2, new_sel);
-                    });
-                }
+                        if !lingering {
+                            break;
+                        }
+                        // A player vanished: look again once its grace period is over.
+                        tokio::time::sleep(grace).await;
+                    }
+                });
             }
             (Some("org.freedesktop.DBus.Properties"), Some("PropertiesChanged")) => {
                 // Уже отфильтровано: path='/org/mpris/MediaPlayer2' и arg0 в add_match
                 if path.as_deref() != Some("/org/mpris/MediaPlayer2") {
                     continue;
                 }
+                // Body: (interface, changed: a{sv}, invalidated: as); only status/metadata matter here.
+                let Ok((_, changed, invalidated)) =
+                    msg.body::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+                else {
+                    continue;
+                };
+                let relevant = |p: &str| p == "PlaybackStatus" || p == "Metadata";
+                if !changed.keys().any(|k| relevant(k)) {
+                    // Invalidated-only: the value isn't in the dict, re-read just this player.
+                    let sender = hdr.sender().ok().flatten().map(ToString::to_string);
+                    if let Some(sender) = sender.filter(|_| invalidated.iter().any(|k| relevant(k))) {
+                        let ctx2 = ctx.clone();
+                        let dbus2 = dbus.clone();
+                        task::spawn(async move {
+                            refresh_status_of_owner(&ctx2, &dbus2, &sender).await;
+                            let new_sel = recompute_selected(&ctx2);
+                            set_selected_and_kick(&ctx2, new_sel);
+                        });
+                    }
+                    continue;
+                }
                 if last_refresh.elapsed() >= Duration::from_millis(REFRESH_DEBOUNCE_MS) {
                     last_refresh = Instant::now();
                     let ctx2 = ctx.clone();
                     task::spawn(async move {
                         if let Err(e) = refresh_statuses(&ctx2).await {
-                            eprintln!("mpris-bridge: refresh statuses failed: {e:#}");
+                            warn!("refresh statuses failed: {e:#}");
                         }
                         let new_sel = recompute_selected(&ctx2);
                         set_selected_and_kick(&ctx2, new_sel);
@@ -1048,9 +3088,108 @@ async fn dbus_main_loop(ctx: Arc<Ctx>) -> Result<()> {
     Ok(())
 }
 
+// Signals are missed while suspended: on resume (PrepareForSleep(false)) reseed and re-emit.
+async fn resume_listener(ctx: Arc<Ctx>) -> Result<()> {
+    let conn = Connection::system().await.context("system bus")?;
+    let dbus = DBusProxy::new(&conn).await?;
+    dbus.add_match_rule(MatchRule::try_from(
+        "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep',path='/org/freedesktop/login1'",
+    )?)
+    .await?;
+    let mut stream = MessageStream::from(&conn);
+    while let Some(msg) = stream.next().await {
+        let msg = msg?;
+        let Ok(hdr) = msg.header() else { continue; };
+        if hdr.member().ok().flatten().is_none_or(|m| m.as_str() != "PrepareForSleep") {
+            continue;
+        }
+        if msg.body::<bool>().unwrap_or(true) {
+            continue; // going to sleep
+        }
+        info!("resumed from suspend, resyncing players");
+        if let Err(e) = seed_players(&ctx).await {
+            warn!("seed after resume failed: {e:#}");
+        }
+        let new_sel = recompute_selected(&ctx);
+        let name = new_sel.as_ref().map(|(n, _)| n.clone());
+        set_selected_sync(&ctx, new_sel);
+        if let Some(name) = name {
+            emit_quick_snapshot(ctx.clone(), name).await;
+        }
+    }
+    Ok(())
+}
+
+async fn hyprctl_json(what: &str) -> Result<Option<serde_json::Value>> {
+    let out = Command::new("hyprctl")
+        .arg(what)
+        .arg("-j")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await?;
+    if out.stdout.is_empty() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_slice(&out.stdout).ok())
+}
+
+// Class of the most recently focused window on the active workspace of the focused monitor.
+async fn focused_monitor_class() -> Result<Option<String>> {
+    let Some(monitors) = hyprctl_json("monitors").await? else {
+        return Ok(None);
+    };
+    let ws = monitors
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|m| m.get("focused").and_then(serde_json::Value::as_bool) == Some(true))
+        .and_then(|m| m.pointer("/activeWorkspace/id"))
+        .and_then(serde_json::Value::as_i64);
+    let Some(ws) = ws else {
+        return Ok(None);
+    };
+    let Some(clients) = hyprctl_json("clients").await? else {
+        return Ok(None);
+    };
+    Ok(clients
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|c| c.pointer("/workspace/id").and_then(serde_json::Value::as_i64) == Some(ws))
+        .min_by_key(|c| c.get("focusHistoryID").and_then(serde_json::Value::as_i64).unwrap_or(i64::MAX))
+        .and_then(|c| c.get("class").and_then(|x| x.as_str()))
+        .map(str::to_string))
+}
+
+async fn apply_hypr_focus(ctx: &Arc<Ctx>) -> Result<()> {
+    let class = if ctx.cfg.selection.per_monitor {
+        focused_monitor_class().await?
+    } else {
+        hyprctl_json("activewindow")
+            .await?
+            .and_then(|v| v.get("class").and_then(|x| x.as_str()).map(str::to_string))
+    };
+    if let Some(class) = class {
+        let new_sel = focus_class(ctx, &class);
+        set_selected_and_kick(ctx, new_sel);
+    }
+    Ok(())
+}
+
+fn focus_class(ctx: &Ctx, class: &str) -> Option<(String, &'static str)> {
+    let hint = map_class_to_hint(class);
+    if hint.is_some() || !ctx.cfg.selection.keep_on_unrelated_focus {
+        *ctx.focus_hint.write().unwrap() = hint;
+    }
+    recompute_selected(ctx)
+}
+
 // Restarting hyprctl -i events on exit
 async fn hypr_focus_listener(ctx: Arc<Ctx>) -> Result<()> {
     use tokio::time::sleep;
+    // Warn once per failure streak rather than every 2s retry
+    let mut failing = false;
     loop {
         let mut child = match Command::new("hyprctl")
             .arg("-i")
@@ -1061,43 +3200,46 @@ async fn hypr_focus_listener(ctx: Arc<Ctx>) -> Result<()> {
         {
             Ok(c) => c,
             Err(e) => {
-                eprintln!("mpris-bridge: hyprctl spawn error: {e:#}");
+                if failing {
+                    debug!("hyprctl spawn error: {e:#}");
+                } else {
+                    warn!("hyprctl spawn error: {e:#} (retrying every 2s)");
+                    failing = true;
+                }
                 sleep(Duration::from_secs(2)).await;
                 continue;
             }
         };
 
-        let stdout = match child.stdout.take() {
-            Some(s) => s,
-            None => {
-                eprintln!("mpris-bridge: hyprctl no stdout");
-                sleep(Duration::from_secs(2)).await;
-                continue;
-            }
+        failing = false;
+        let Some(stdout) = child.stdout.take() else {
+            warn!("hyprctl no stdout");
+            sleep(Duration::from_secs(2)).await;
+            continue;
         };
         let mut lines = BufReader::new(stdout).lines();
 
-        while let Some(line) = lines.next_line().await? {
-            if line.starts_with("activewindow>>") {
-                let out = Command::new("hyprctl")
-                    .arg("activewindow")
-                    .arg("-j")
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::null())
-                    .output()
-                    .await?;
-                if !out.stdout.is_empty() {
-                    if let Ok(v) = serde_json::from_slice::<serde_json::Value>(&out.stdout) {
-                        if let Some(class) = v.get("class").and_then(|x| x.as_str()) {
-                            let hint = map_class_to_hint(class);
-                            {
-                                *ctx.focus_hint.write().unwrap() = hint;
-                            }
-                            let new_sel = recompute_selected(&ctx);
-                            set_selected_and_kick(&ctx, new_sel);
-                        }
+        // Debounce: only the last focus event within the window is applied.
+        // With per_monitor, monitor/workspace switches also move the focus hint.
+        let per_monitor = ctx.cfg.selection.per_monitor;
+        let debounce = Duration::from_millis(ctx.cfg.selection.focus_debounce_ms);
+        let deadline = sleep(debounce);
+        tokio::pin!(deadline);
+        let mut pending = false;
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else { break; };
+                    let monitor_event = line.starts_with("focusedmon>>") || line.starts_with("workspace>>");
+                    if line.starts_with("activewindow>>") || (per_monitor && monitor_event) {
+                        pending = true;
+                        deadline.as_mut().reset(Instant::now() + debounce);
                     }
                 }
+                () = &mut deadline, if pending => {
+                    pending = false;
+                    apply_hypr_focus(&ctx).await?;
+                }
             }
         }
 
@@ -1108,7 +3250,47 @@ async fn hypr_focus_listener(ctx: Arc<Ctx>) -> Result<()> {
 
 // ------------------------- Seed/Refresh -------------------------
 
-async fn seed_players(ctx: &Arc<Ctx>) -> Result<()> {
+// Players missing from a fresh listing stay until `grace` has passed since they were last listed.
+fn merge_players(
+    last_seen: &mut HashMap<String, Instant>,
+    listed: &HashSet<String>,
+    now: Instant,
+    grace: Duration,
+) -> HashSet<String> {
+    for p in listed {
+        last_seen.insert(p.clone(), now);
+    }
+    last_seen.retain(|p, seen| listed.contains(p) || now.duration_since(*seen) < grace);
+    last_seen.keys().cloned().collect()
+}
+
+// selection.max_players: keep the selected player, then playing ones, then by `priority` order.
+fn cap_players(ctx: &Ctx, players: HashSet<String>) -> HashSet<String> {
+    let Some(max) = ctx.cfg.selection.max_players.filter(|m| players.len() > *m) else {
+        ctx.players_dropped.store(0, Ordering::SeqCst);
+        return players;
+    };
+    let selected = ctx.selected.read().unwrap().clone();
+    let status = ctx.status.read().unwrap().clone();
+    let priority = &ctx.cfg.selection.priority;
+    let mut ranked: Vec<String> = players.into_iter().collect();
+    ranked.sort_by_cached_key(|p| {
+        (
+            selected.as_ref() != Some(p),
+            !status.get(p).is_some_and(|s| is_active_status(ctx, s)),
+            priority.iter().position(|want| p.starts_with(want.as_str())).unwrap_or(usize::MAX),
+            p.clone(),
+        )
+    });
+    let dropped = ranked.split_off(max);
+    if ctx.players_dropped.swap(dropped.len(), Ordering::SeqCst) != dropped.len() {
+        warn!("tracking {max} players (selection.max_players), ignoring {}: {}", dropped.len(), dropped.join(", "));
+    }
+    ranked.into_iter().collect()
+}
+
+// Ok(true) when some players are only kept by the exit grace (re-seed later to drop them).
+async fn seed_players(ctx: &Arc<Ctx>) -> Result<bool> {
     let out = Command::new("playerctl")
         .arg("-l")
         .stdout(Stdio::piped())
@@ -1131,61 +3313,263 @@ async fn seed_players(ctx: &Arc<Ctx>) -> Result<()> {
             ps.insert(name);
         }
     }
-    *ctx.players.write().unwrap() = ps;
+    let grace = Duration::from_millis(ctx.cfg.selection.player_exit_grace_ms);
+    let merged = merge_players(&mut ctx.last_seen.write().unwrap(), &ps, Instant::now(), grace);
+    let lingering = merged.len() > ps.len();
+    let merged = cap_players(ctx, merged);
+    *ctx.players.write().unwrap() = merged;
     refresh_statuses(ctx).await?;
-    Ok(())
+    Ok(lingering)
+}
+
+// `playerctl status` (or `position`) result for one player; a spawn error, non-zero exit
+// ("No players found" for a player that vanished) or empty output yields None.
+fn parse_status_output(out: std::io::Result<std::process::Output>) -> Option<String> {
+    let out = out.ok().filter(|o| o.status.success())?;
+    let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (!s.is_empty()).then_some(s)
+}
+
+async fn query_status(ctx: &Ctx, player: &str) -> Option<String> {
+    let _permit = query_permit(ctx).await;
+    let out = playerctl(ctx, player)
+        .arg("status")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    parse_status_output(out)
 }
 
 async fn refresh_statuses(ctx: &Arc<Ctx>) -> Result<()> {
     let players: Vec<String> = ctx.players.read().unwrap().iter().cloned().collect();
     let mut st = HashMap::new();
     for p in players {
-        let out = Command::new("playerctl")
-            .arg("-p")
-            .arg(&p)
-            .arg("status")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .output()
-            .await?;
-        let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
-        if !s.is_empty() {
+        // A player may vanish between listing and querying; skip it, keep the rest.
+        if let Some(s) = query_status(ctx, &p).await {
             st.insert(p, s);
+        } else {
+            debug!("no status for {p}, skipping");
         }
     }
     *ctx.status.write().unwrap() = st;
+    if ctx.cfg.selection.require_audio {
+        refresh_audible_apps(ctx).await;
+    }
     Ok(())
 }
 
+// Without pactl (or a sound server) the audio preference is simply off.
+async fn refresh_audible_apps(ctx: &Ctx) {
+    let out = Command::new("pactl")
+        .arg("list")
+        .arg("sink-inputs")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    let apps = match out {
+        Ok(o) if o.status.success() => Some(parse_audible_apps(&String::from_utf8_lossy(&o.stdout))),
+        Ok(_) | Err(_) => {
+            debug!("pactl unavailable, ignoring selection.require_audio");
+            None
+        }
+    };
+    *ctx.audible_apps.write().unwrap() = apps;
+}
+
+// Re-read the status of the player whose bus name `sender` (a unique name) currently owns.
+async fn refresh_status_of_owner(ctx: &Arc<Ctx>, dbus: &DBusProxy<'_>, sender: &str) {
+    let players: Vec<String> = ctx.players.read().unwrap().iter().cloned().collect();
+    for p in players {
+        let Ok(bus) = BusName::try_from(mpris_bus_name(&p)) else {
+            continue;
+        };
+        if !dbus.get_name_owner(bus).await.is_ok_and(|o| o.as_str() == sender) {
+            continue;
+        }
+        let fresh = query_status(ctx, &p).await;
+        if let Some(s) = fresh {
+            ctx.status.write().unwrap().insert(p, s);
+        } else {
+            ctx.status.write().unwrap().remove(&p);
+        }
+        return;
+    }
+}
+
 // ------------------------- Config I/O -------------------------
 
-async fn read_config() -> Result<Config> {
+fn config_path() -> Result<PathBuf> {
     let cfg_dir = dirs::config_dir().context("no XDG_CONFIG_HOME")?;
-    let path = cfg_dir.join("mpris-bridge").join("config.toml");
-    let text = fs::read_to_string(&path).with_context(|| format!("reading config {}", path.display()))?;
-    let cfg: Config = toml::from_str(&text).context("parsing toml")?;
-    Ok(cfg)
+    Ok(cfg_dir.join("mpris-bridge").join("config.toml"))
+}
+
+// None when there is no config file (defaults apply); a malformed file is still an error.
+fn read_config() -> Result<Option<Config>> {
+    let path = config_path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading config {}", path.display())),
+    };
+    let cfg: Config = toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(Some(cfg))
+}
+
+// Settings with a fixed set of values: a typo falls back to the default, so say so.
+fn warn_unknown_values(cfg: &Config) {
+    let sel = &cfg.selection;
+    if !matches!(sel.focus_source.as_str(), "hyprland" | "playback") {
+        warn!("unknown selection.focus_source {:?} (expected hyprland|playback), using hyprland", sel.focus_source);
+    }
+    if !matches!(sel.policy.as_str(), "ordered" | "weighted") {
+        warn!("unknown selection.policy {:?} (expected ordered|weighted), using ordered", sel.policy);
+    }
+}
+
+// --write-default-config: the commented example config, never over an existing file.
+fn write_default_config() -> Result<PathBuf> {
+    let path = config_path()?;
+    anyhow::ensure!(!path.exists(), "{} already exists", path.display());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, include_str!("../examples/config/config.toml"))?;
+    Ok(path)
+}
+
+// ------------------------- Logging -------------------------
+
+// stderr logger; journald picks it up under systemd. JSON lines carry timestamp, level, target and fields.
+fn init_logging(cfg: &Logging) {
+    use tracing_subscriber::filter::LevelFilter;
+    let level = cfg.level.parse::<LevelFilter>().unwrap_or_else(|_| {
+        eprintln!("mpris-bridge: unknown logging.level {:?}, using info", cfg.level);
+        LevelFilter::INFO
+    });
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr);
+    if cfg.format == "json" {
+        builder.json().init();
+    } else {
+        builder.init();
+    }
+}
+
+// ------------------------- Shutdown -------------------------
+
+const SHUTDOWN_ART_GRACE_MS: u64 = 1000;
+
+async fn shutdown_signal() {
+    let (Ok(mut term), Ok(mut int)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = int.recv() => {}
+    }
+}
+
+// Give in-flight art downloads a moment, then drop leftovers so the cache holds only complete files.
+async fn shutdown(ctx: &Ctx) {
+    info!("shutting down");
+    let deadline = Instant::now() + Duration::from_millis(SHUTDOWN_ART_GRACE_MS);
+    while ctx.art_inflight.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    remove_partial_downloads(ctx);
+    let _ = fs::remove_file(&ctx.socket_path);
 }
 
 // ------------------------- Main -------------------------
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cfg = read_config().await?;
+    let mut args: Vec<String> = std::env::args().collect();
+    let instance = mpris_bridge::take_instance_flag(&mut args).map_err(anyhow::Error::msg)?;
+    if args.iter().any(|a| a == "--write-default-config") {
+        let path = write_default_config()?;
+        println!("wrote {}", path.display());
+        return Ok(());
+    }
+    let loaded = read_config()?;
+    let using_defaults = loaded.is_none();
+    let mut cfg = loaded.unwrap_or_default();
+    cfg.instance = instance;
+    if args.iter().any(|a| a == "--trace-dbus") {
+        cfg.logging.trace_dbus = true;
+    }
+    if cfg.logging.trace_dbus && !matches!(cfg.logging.level.as_str(), "debug" | "trace") {
+        cfg.logging.level = "debug".into();
+    }
+    init_logging(&cfg.logging);
+    warn_unknown_values(&cfg);
+    if using_defaults {
+        info!("no config file at {}, using defaults", config_path()?.display());
+    }
     let (sel_tx, sel_rx) = watch::channel::<Option<String>>(None);
     let ctx = Arc::new(Ctx::new(cfg, sel_tx.clone()));
     ensure_dirs(&ctx);
+    if !ctx.blank_thumbnail.is_empty() && !Path::new(&ctx.blank_thumbnail).is_file() {
+        warn!("blank thumbnail {} does not exist; set art.blank_thumbnail (\"\" for none)", ctx.blank_thumbnail);
+    }
+
+    if ctx.cfg.output.defer_first_write {
+        // A snapshot left over from a previous run would defeat the point.
+        let _ = fs::remove_file(&ctx.snapshot_path);
+    }
+
+    if ctx.cfg.art.async_pipeline {
+        let rx = ctx.art_rx.lock().unwrap().take();
+        if let Some(rx) = rx {
+            task::spawn(art_worker(ctx.clone(), rx));
+        }
+    }
 
-    // Initial blank snapshot
-    let init = UiState::empty(&ctx.default_cover.to_string_lossy());
-    write_state(&ctx, &init).await?;
+    // Startup scan: if something is already playing, its track is the first snapshot.
+    if let Err(e) = seed_players(&ctx).await {
+        debug!("startup seed failed: {e:#}");
+    }
+    let initial = recompute_selected(&ctx);
+    set_selected_sync(&ctx, initial.clone());
+    if let Some((name, _)) = initial {
+        emit_quick_snapshot(ctx.clone(), name).await;
+    }
+
+    // Initial blank snapshot (no player, or the quick snapshot failed)
+    if ctx.last_state.read().unwrap().is_none() {
+        let init = UiState::empty(&ctx.blank_thumbnail);
+        write_placeholder(&ctx, &init)?;
+    }
+    if ctx.cfg.output.defer_first_write {
+        let defer_ctx = ctx.clone();
+        task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(DEFER_FIRST_WRITE_TIMEOUT_MS)).await;
+            if !defer_ctx.first_write_done.load(Ordering::SeqCst) {
+                let mut st = UiState::empty(&defer_ctx.blank_thumbnail);
+                let selected = defer_ctx.selected.read().unwrap().clone();
+                if let Some(name) = selected {
+                    st.bus_name = mpris_bus_name(&name);
+                    st.name = name;
+                }
+                if let Err(e) = write_state(&defer_ctx, &st) {
+                    warn!("write_state error: {e:#}");
+                }
+            }
+        });
+    }
 
     // SIGHUP placeholder (hot-reload can be added later)
     let _ctx_for_signal = ctx.clone();
     task::spawn(async move {
         if let Ok(mut hup) = signal(SignalKind::hangup()) {
             while hup.recv().await.is_some() {
-                eprintln!("mpris-bridge: SIGHUP received (reload TBD)");
+                info!("SIGHUP received (reload TBD)");
             }
         }
     });
@@ -1194,30 +3578,601 @@ async fn main() -> Result<()> {
     let fm_ctx = ctx.clone();
     task::spawn(async move {
         if let Err(e) = follower_manager(fm_ctx, sel_rx).await {
-            eprintln!("mpris-bridge: follower manager error: {e:#}");
+            error!("follower manager error: {e:#}");
         }
     });
 
+    // Extra outputs: one fixed-player follower each
+    for sink in &ctx.extra_outputs {
+        task::spawn(extra_follower_manager(ctx.clone(), sink.clone()));
+    }
+
+    if let Some(ms) = ctx.cfg.output.poll_position_ms.filter(|ms| *ms > 0) {
+        task::spawn(position_poller(ctx.clone(), Duration::from_millis(ms)));
+    }
+
     // IPC server (blocking Unix socket on a dedicated thread pool task)
     let ipc_ctx = ctx.clone();
     task::spawn_blocking(move || {
-        if let Err(e) = ipc_server_blocking(ipc_ctx) {
-            eprintln!("mpris-bridge: ipc server error: {e:#}");
+        if let Err(e) = ipc_server_blocking(&ipc_ctx) {
+            error!("ipc server error: {e:#}");
         }
     });
+    if let Some(addr) = ctx.cfg.output.ipc_tcp_listen.clone() {
+        let tcp_ctx = ctx.clone();
+        task::spawn_blocking(move || {
+            if let Err(e) = ipc_tcp_server_blocking(&tcp_ctx, &addr) {
+                error!("ipc tcp server error ({addr}): {e:#}");
+            }
+        });
+    }
 
     // Hyprland focus listener with self-restart
-    let focus_ctx = ctx.clone();
-    task::spawn(async move {
-        if let Err(e) = hypr_focus_listener(focus_ctx).await {
-            eprintln!("mpris-bridge: hypr focus listener failed: {e:#}");
+    if ctx.cfg.selection.focus_source != "playback" {
+        let focus_ctx = ctx.clone();
+        task::spawn(async move {
+            if let Err(e) = hypr_focus_listener(focus_ctx).await {
+                error!("hypr focus listener failed: {e:#}");
+            }
+        });
+    }
+
+    if ctx.cfg.selection.resync_on_resume {
+        let resume_ctx = ctx.clone();
+        task::spawn(async move {
+            if let Err(e) = resume_listener(resume_ctx).await {
+                debug!("resume listener unavailable: {e:#}");
+            }
+        });
+    }
+
+    // D-Bus events listener with autoreconnect, until SIGTERM/SIGINT
+    tokio::select! {
+        res = dbus_listener(ctx.clone()) => {
+            if let Err(e) = res {
+                error!("dbus listener failed: {e:#}");
+            }
         }
-    });
+        () = shutdown_signal() => {}
+    }
+    shutdown(&ctx).await;
 
-    // D-Bus events listener with autoreconnect
-    if let Err(e) = dbus_listener(ctx.clone()).await {
-        eprintln!("mpris-bridge: dbus listener failed: {e:#}");
+    // The blocking IPC thread never returns, so dropping the runtime would hang.
+    std::process::exit(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_ctx() -> Ctx {
+        ctx_with(Config::default())
     }
 
-    Ok(())
-}
\ No newline at end of file
+    fn ctx_with(cfg: Config) -> Ctx {
+        let (tx, _rx) = watch::channel(None);
+        Ctx::new(cfg, tx)
+    }
+
+    // Fresh per-process scratch directory; tests remove it when done.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mpris-bridge-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn add_player(ctx: &Ctx, name: &str, status: &str) {
+        ctx.players.write().unwrap().insert(name.into());
+        ctx.status.write().unwrap().insert(name.into(), status.into());
+    }
+
+    fn weighted_ctx(weights: &[(&str, i64)]) -> Ctx {
+        let mut cfg = Config::default();
+        cfg.selection.policy = "weighted".into();
+        cfg.selection.weights = weights.iter().map(|(p, w)| ((*p).to_string(), *w)).collect();
+        ctx_with(cfg)
+    }
+
+    #[test]
+    fn weighted_policy_scores_players() {
+        let name = |c: Option<(String, &str)>| c.map(|(n, _)| n);
+
+        // Playing (10 + 2 + recency 1) beats a paused higher weight (3)...
+        let ctx = weighted_ctx(&[("spotify", 3), ("firefox", 2)]);
+        add_player(&ctx, "spotify", "Paused");
+        add_player(&ctx, "firefox.instance_1", "Playing");
+        assert_eq!(name(recompute_selected(&ctx)), Some("firefox.instance_1".into()));
+        // ...unless the weight outweighs the playing bonus.
+        let ctx = weighted_ctx(&[("spotify", 20), ("firefox", 2)]);
+        add_player(&ctx, "spotify", "Paused");
+        add_player(&ctx, "firefox.instance_1", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("spotify".into(), "weighted")));
+
+        // Both playing: focus (5) beats a weight lead of 1.
+        let ctx = weighted_ctx(&[("spotify", 3), ("firefox", 2)]);
+        add_player(&ctx, "spotify", "Playing");
+        add_player(&ctx, "firefox.instance_1", "Playing");
+        *ctx.focus_hint.write().unwrap() = Some("firefox".into());
+        assert_eq!(name(recompute_selected(&ctx)), Some("firefox.instance_1".into()));
+
+        // Nothing playing: the highest weight; unlisted players weigh 1.
+        let ctx = weighted_ctx(&[("vlc", 2)]);
+        add_player(&ctx, "mpv", "Paused");
+        add_player(&ctx, "vlc", "Stopped");
+        assert_eq!(name(recompute_selected(&ctx)), Some("vlc".into()));
+    }
+
+    #[test]
+    fn playback_focus_is_the_latest_started_player() {
+        let mut ctx = weighted_ctx(&[("spotify", 3), ("firefox", 2)]);
+        ctx.cfg.selection.focus_source = "playback".into();
+        add_player(&ctx, "spotify", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("spotify".into(), "weighted")));
+        std::thread::sleep(Duration::from_millis(2));
+        add_player(&ctx, "firefox", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("firefox".into(), "weighted")));
+        // Selection doesn't write the compositor's hint
+        assert_eq!(*ctx.focus_hint.read().unwrap(), None);
+    }
+
+    #[test]
+    fn weighted_policy_without_fallback_keeps_remembered_player() {
+        let mut ctx = weighted_ctx(&[("vlc", 5)]);
+        ctx.cfg.selection.fallback = "none".into();
+        ctx.cfg.selection.priority = vec![];
+        add_player(&ctx, "mpv", "Paused");
+        add_player(&ctx, "vlc", "Paused");
+        *ctx.last_selected.write().unwrap() = Some("mpv".into());
+        // vlc weighs more but is neither remembered, focused, pinned nor in priority
+        assert_eq!(recompute_selected(&ctx), Some(("mpv".into(), "weighted")));
+        *ctx.last_selected.write().unwrap() = None;
+        assert_eq!(recompute_selected(&ctx), None);
+    }
+
+    #[test]
+    fn max_players_keeps_selected_playing_and_priority() {
+        let mut cfg = Config::default();
+        cfg.selection.max_players = Some(3);
+        let ctx = ctx_with(cfg);
+        let all: HashSet<String> = ["spam.1", "spam.2", "spam.3", "vlc", "mpv", "chromium"].iter().map(|s| (*s).to_string()).collect();
+        *ctx.selected.write().unwrap() = Some("chromium".into());
+        ctx.status.write().unwrap().insert("spam.2".into(), "Playing".into());
+        let kept = cap_players(&ctx, all);
+        let mut kept: Vec<_> = kept.into_iter().collect();
+        kept.sort();
+        assert_eq!(kept, ["chromium", "spam.2", "vlc"]);
+        assert_eq!(ctx.players_dropped.load(Ordering::SeqCst), 3);
+        // Under the cap: untouched
+        let few: HashSet<String> = ["vlc".to_string()].into();
+        assert_eq!(cap_players(&ctx, few.clone()), few);
+    }
+
+    #[test]
+    fn toggle_players_flips_and_locks() {
+        let ctx = test_ctx();
+        add_player(&ctx, "spotify", "Playing");
+        add_player(&ctx, "mpv", "Paused");
+        assert_eq!(toggle_target(&ctx, "spotify", "mpv").unwrap(), "spotify");
+        set_selected_sync(&ctx, Some(("spotify".into(), "priority")));
+        assert_eq!(toggle_target(&ctx, "spotify", "mpv").unwrap(), "mpv");
+
+        // The lock beats the playing player until it expires
+        *ctx.selection_lock.write().unwrap() = Some(("mpv".into(), Instant::now() + Duration::from_secs(10)));
+        assert_eq!(recompute_selected(&ctx), Some(("mpv".into(), "locked")));
+        *ctx.selection_lock.write().unwrap() = Some(("mpv".into(), Instant::now() - Duration::from_secs(1)));
+        assert_eq!(recompute_selected(&ctx).map(|(n, _)| n), Some("spotify".into()));
+
+        assert!(toggle_target(&ctx, "vlc", "chromium").is_err());
+    }
+
+    #[test]
+    fn unrelated_focus_keeps_selection() {
+        let ctx = test_ctx();
+        add_player(&ctx, "firefox.instance_1", "Playing");
+        add_player(&ctx, "spotify", "Playing");
+        let sel = focus_class(&ctx, "Spotify");
+        assert_eq!(sel.as_ref().map(|(n, _)| n.as_str()), Some("spotify"));
+        set_selected_sync(&ctx, sel);
+        let sel = focus_class(&ctx, "kitty");
+        assert_eq!(sel.map(|(n, _)| n), Some("spotify".into()));
+    }
+
+    #[test]
+    fn events_ring_keeps_newest_states() {
+        let mut cfg = Config::default();
+        cfg.output.events_memory_ring = Some(3);
+        let dir = scratch_dir("ring");
+        cfg.output.snapshot_path = Some(dir.join("state.json").to_string_lossy().into());
+        cfg.output.events_path = Some(String::new());
+        let ctx = ctx_with(cfg);
+        for title in ["a", "b", "c", "d"] {
+            let mut st = UiState::empty("");
+            st.title = title.into();
+            write_state(&ctx, &st).unwrap();
+        }
+        let titles = |n| recent_events(&ctx, n).unwrap().iter().map(|v| v["title"].to_string()).collect::<Vec<_>>();
+        assert_eq!(titles(10), ["\"b\"", "\"c\"", "\"d\""]);
+        assert_eq!(titles(1), ["\"d\""]);
+        assert!(!dir.join("events.jsonl").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn freeze_holds_back_extra_outputs() {
+        let dir = scratch_dir("freeze");
+        let mut cfg = Config::default();
+        cfg.output.snapshot_path = Some(dir.join("state.json").to_string_lossy().into());
+        cfg.output.events_path = Some(String::new());
+        let extra_path = dir.join("vlc.json");
+        cfg.output.extra = vec![ExtraOutput {
+            player: "vlc".into(),
+            snapshot_path: extra_path.to_string_lossy().into(),
+            events_path: String::new(),
+        }];
+        let ctx = ctx_with(cfg);
+        let sink = ctx.extra_outputs[0].clone();
+        set_frozen(&ctx, true).unwrap();
+        for title in ["a", "b"] {
+            let mut st = UiState::empty("");
+            st.title = title.into();
+            write_follower_state(&ctx, Some(&sink), &st).unwrap();
+        }
+        write_state(&ctx, &UiState::empty("")).unwrap();
+        // A write numbered before the unfreeze, still waiting for the lock
+        let racing = ctx.write_seq.fetch_add(1, Ordering::SeqCst) + 1;
+        assert!(!extra_path.exists());
+        set_frozen(&ctx, false).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&extra_path).unwrap()).unwrap();
+        assert_eq!(written["title"], "b");
+        // The held main state keeps its older number, so the racing write isn't dropped as stale
+        assert!(*ctx.write_lock.lock().unwrap() < racing);
+        assert!(dir.join("state.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaced_extra_follower_does_not_clear_its_successor() {
+        let mut cfg = Config::default();
+        cfg.output.extra = vec![ExtraOutput {
+            player: "vlc".into(),
+            snapshot_path: String::new(),
+            events_path: String::new(),
+        }];
+        let ctx = ctx_with(cfg);
+        let sink = ctx.extra_outputs[0].clone();
+        let old = follower_generation(&ctx, Some(&sink));
+        sink.generation.fetch_add(1, Ordering::SeqCst);
+        let current = follower_generation(&ctx, Some(&sink));
+        set_follower_alive(&ctx, Some(&sink), current, true);
+        set_follower_alive(&ctx, Some(&sink), old, false);
+        assert!(sink.alive.load(Ordering::SeqCst));
+        // The main follower's generation is separate
+        assert_eq!(follower_generation(&ctx, None), 0);
+    }
+
+    #[test]
+    fn custom_active_status_counts_as_playing() {
+        let mut cfg = Config::default();
+        cfg.selection.active_statuses = vec!["Playing".into(), "Active".into()];
+        cfg.presentation.status_icons.playing = Some("▶".into());
+        let ctx = ctx_with(cfg);
+        add_player(&ctx, "firefox.instance_1_240", "Paused");
+        add_player(&ctx, "customplayer", "Active");
+        assert_eq!(recompute_selected(&ctx).map(|(p, _)| p).as_deref(), Some("customplayer"));
+        // ...and shows the playing icon
+        let status = normalize_status(&ctx, "Active");
+        assert_eq!(status_icon(&ctx.cfg.presentation, "customplayer", &status), "▶");
+
+        // Default config: "Active" is not playing, so priority wins.
+        let ctx = test_ctx();
+        add_player(&ctx, "firefox.instance_1_240", "Paused");
+        add_player(&ctx, "customplayer", "Active");
+        assert_eq!(recompute_selected(&ctx).map(|(p, _)| p).as_deref(), Some("firefox.instance_1_240"));
+    }
+
+    #[test]
+    fn pin_boosts_until_expiry() {
+        let ctx = test_ctx();
+        add_player(&ctx, "firefox.instance_1_240", "Paused");
+        add_player(&ctx, "mpv", "Paused");
+        assert_eq!(recompute_selected(&ctx).map(|(p, _)| p).as_deref(), Some("firefox.instance_1_240"));
+
+        *ctx.pinned.write().unwrap() = Some(("mpv".into(), Instant::now() + Duration::from_secs(30)));
+        assert_eq!(recompute_selected(&ctx), Some(("mpv".into(), "pinned")));
+
+        *ctx.pinned.write().unwrap() = Some(("mpv".into(), Instant::now()));
+        assert_eq!(recompute_selected(&ctx).map(|(p, _)| p).as_deref(), Some("firefox.instance_1_240"));
+    }
+
+    #[test]
+    fn vanished_player_status_is_skipped() {
+        use std::os::unix::process::ExitStatusExt;
+        let output = |code: i32, stdout: &str, stderr: &str| std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        assert_eq!(parse_status_output(Ok(output(0, "Playing\n", ""))).as_deref(), Some("Playing"));
+        assert_eq!(parse_status_output(Ok(output(1, "", "No players found\n"))), None);
+        assert_eq!(parse_status_output(Ok(output(0, "", ""))), None);
+        assert_eq!(parse_status_output(Err(std::io::ErrorKind::NotFound.into())), None);
+    }
+
+    #[test]
+    fn control_blacklisted_player_is_selected_but_not_controlled() {
+        let mut cfg = Config::default();
+        cfg.selection.control_blacklist = vec!["systemsounds".into()];
+        let ctx = Arc::new(ctx_with(cfg));
+        add_player(&ctx, "systemsounds", "Playing");
+        add_player(&ctx, "mpv", "Paused");
+
+        let sel = recompute_selected(&ctx);
+        assert_eq!(sel, Some(("systemsounds".into(), "playing-only")));
+        set_selected_sync(&ctx, sel);
+
+        let err = dispatch_ipc(&ctx, IpcCmd::PlayPause { player: None }).unwrap_err();
+        assert_eq!(format!("{err:#}"), "player is control-blacklisted");
+        let err = dispatch_ipc(&ctx, IpcCmd::Next { player: Some("systemsounds".into()) }).unwrap_err();
+        assert_eq!(format!("{err:#}"), "player is control-blacklisted");
+    }
+
+    #[test]
+    fn player_exit_grace_survives_quick_reappear() {
+        let grace = Duration::from_millis(500);
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let spotify: HashSet<String> = ["spotify".to_string()].into();
+        let none = HashSet::new();
+        let mut seen = HashMap::new();
+
+        assert_eq!(merge_players(&mut seen, &spotify, at(0), grace), spotify);
+        // Drops off the bus on a track skip, comes back within the grace period.
+        assert_eq!(merge_players(&mut seen, &none, at(100), grace), spotify);
+        assert_eq!(merge_players(&mut seen, &spotify, at(300), grace), spotify);
+        // Gone for good: kept until the grace since the last listing runs out.
+        assert_eq!(merge_players(&mut seen, &none, at(700), grace), spotify);
+        assert_eq!(merge_players(&mut seen, &none, at(800), grace), none);
+    }
+
+    #[test]
+    fn field_names_rename_output_keys() {
+        let mut cfg = Config::default();
+        cfg.output.field_names = [("title", "track"), ("artist", "performer")]
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        let ctx = ctx_with(cfg);
+        let mut st = UiState::empty("");
+        st.title = "Song".into();
+        st.artist = "Band".into();
+        let v: serde_json::Value = serde_json::from_str(&state_json(&ctx, &st, false).unwrap()).unwrap();
+        assert_eq!(v["track"], "Song");
+        assert_eq!(v["performer"], "Band");
+        assert!(v.get("title").is_none());
+        assert_eq!(v["positionStr"], "0:00");
+    }
+
+    #[test]
+    fn concurrent_writes_leave_the_latest_state() {
+        let mut cfg = Config::default();
+        let dir = scratch_dir("writes");
+        cfg.output.snapshot_path = Some(dir.join("state.json").to_string_lossy().into());
+        cfg.output.events_path = Some(String::new());
+        let ctx = Arc::new(ctx_with(cfg));
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let ctx = ctx.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let mut st = UiState::empty("");
+                        st.title = format!("{t}-{i}");
+                        write_state(&ctx, &st).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        // The highest sequence is always written, and the file matches the last committed state
+        assert_eq!(*ctx.write_lock.lock().unwrap(), 100);
+        let last = ctx.last_state.read().unwrap().clone().unwrap();
+        let on_disk: serde_json::Value = serde_json::from_str(&fs::read_to_string(&ctx.snapshot_path).unwrap()).unwrap();
+        assert_eq!(on_disk["title"], last.title.as_str());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn events_timestamp_is_rfc3339_utc() {
+        assert_eq!(rfc3339_utc(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(rfc3339_utc(leap_day), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn most_recently_started_player_wins_ties() {
+        let ctx = test_ctx();
+        add_player(&ctx, "foo", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("foo".into(), "playing-only")));
+        std::thread::sleep(Duration::from_millis(2));
+        add_player(&ctx, "bar", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("bar".into(), "playing-only")));
+        // Restarting foo makes it the most recent again
+        add_player(&ctx, "foo", "Paused");
+        recompute_selected(&ctx);
+        add_player(&ctx, "foo", "Playing");
+        assert_eq!(recompute_selected(&ctx), Some(("foo".into(), "playing-only")));
+    }
+
+    #[test]
+    fn source_kind_from_xesam_url() {
+        assert_eq!(source_kind("https://www.youtube.com/watch?v=abc"), "youtube");
+        assert_eq!(source_kind("https://music.youtube.com/watch?v=abc&list=x"), "youtube");
+        assert_eq!(source_kind("file:///home/user/Music/a.flac"), "file");
+        assert_eq!(source_kind("https://radio.example.com/stream.mp3"), "http-stream");
+        assert_eq!(source_kind(""), "local");
+        assert_eq!(source_kind("spotify:track:123"), "other");
+    }
+
+    #[test]
+    fn round_corners_masks_only_the_corners() {
+        let mut img = image::RgbaImage::from_pixel(40, 20, image::Rgba([200, 100, 50, 255]));
+        round_corners(&mut img, 8);
+        for (x, y) in [(0, 0), (39, 0), (0, 19), (39, 19)] {
+            assert_eq!(img.get_pixel(x, y)[3], 0);
+        }
+        assert_eq!(img.get_pixel(20, 0)[3], 255);
+        assert_eq!(img.get_pixel(0, 10)[3], 255);
+        assert_eq!(img.get_pixel(8, 8)[3], 255);
+        // Radius larger than half the short side is clamped
+        let mut small = image::RgbaImage::from_pixel(10, 10, image::Rgba([0, 0, 0, 255]));
+        round_corners(&mut small, 50);
+        assert_eq!(small.get_pixel(5, 5)[3], 255);
+        assert_eq!(small.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn art_dimensions_measure_the_resolved_cover() {
+        let dir = scratch_dir("dims");
+        let ctx = test_ctx();
+        // Named .jpg like the cache entries, PNG inside
+        let cover = dir.join("cover.jpg");
+        image::RgbaImage::new(30, 12).save_with_format(&cover, image::ImageFormat::Png).unwrap();
+        assert_eq!(art_dimensions(&ctx, &cover), (30, 12));
+        assert_eq!(art_dimensions(&ctx, &ctx.default_cover), (0, 0));
+        assert_eq!(art_dimensions(&ctx, &ctx.error_cover), (0, 0));
+        // Same file rewritten with another image: measured again
+        std::thread::sleep(Duration::from_millis(20));
+        image::RgbaImage::new(8, 8).save_with_format(&cover, image::ImageFormat::Png).unwrap();
+        assert_eq!(art_dimensions(&ctx, &cover), (8, 8));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unwritable_current_cover_falls_back_to_source() {
+        let dir = scratch_dir("cover");
+        let cover = dir.join("cover.png");
+        fs::write(&cover, b"png").unwrap();
+        // A regular file as the parent directory: fails even when running as root
+        let blocker = dir.join("not-a-dir");
+        fs::write(&blocker, b"").unwrap();
+        let mut cfg = Config::default();
+        cfg.art.default_image = Some(cover.to_string_lossy().into());
+        cfg.art.current_path = Some(blocker.join("image.jpg").to_string_lossy().into());
+        let ctx = ctx_with(cfg);
+
+        let err = ensure_current_cover(&ctx, &cover, &ctx.current_cover).unwrap_err();
+        assert!(format!("{err:#}").contains("not-a-dir/image.jpg"), "{err:#}");
+        assert!(format!("{err:#}").contains("os error"), "{err:#}");
+        let (thumbnail, _) = update_art(&ctx, "mpv", "").await.unwrap();
+        assert_eq!(thumbnail, cover.to_string_lossy());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replacing_the_follower_reaps_the_old_one() {
+        let ctx = test_ctx();
+        let mut slot = None;
+        let mut pids = vec![];
+        for name in ["a", "b", "b", "c"] {
+            replace_follower(&ctx, &mut slot, Some(name.to_string()), |_| {
+                Ok(Command::new("sleep").arg("30").kill_on_drop(true).spawn()?)
+            })
+            .await;
+            pids.push(slot.as_ref().and_then(Child::id).unwrap());
+            assert_eq!(ctx.followers_live.load(Ordering::SeqCst), 1);
+            let running: Vec<_> = pids.iter().filter(|p| Path::new(&format!("/proc/{p}")).exists()).collect();
+            assert_eq!(running, [pids.last().unwrap()]);
+        }
+        // A reader of a replaced follower exiting doesn't clear the current one's flag
+        let generation = ctx.follower_gen.load(Ordering::SeqCst);
+        set_follower_alive(&ctx, None, generation, true);
+        set_follower_alive(&ctx, None, generation - 1, false);
+        assert!(ctx.follower_alive.load(Ordering::SeqCst));
+        replace_follower(&ctx, &mut slot, None, |_| unreachable!()).await;
+        assert!(slot.is_none());
+        assert_eq!(ctx.followers_live.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn empty_artist_falls_back_to_album_artist() {
+        let line = "Playing|mpv|Symphony No. 5||1|||Fifth|Berliner Philharmoniker|file:///a|b.flac";
+        let parts: Vec<String> = line.splitn(METADATA_FIELDS, '|').map(ToString::to_string).collect();
+        assert_eq!(parts[9], "file:///a|b.flac");
+        let ctx = test_ctx();
+        assert_eq!(metadata_artist(&ctx, &parts), "Berliner Philharmoniker");
+
+        let mut cfg = Config::default();
+        cfg.presentation.artist_fallback_album_artist = false;
+        assert_eq!(metadata_artist(&ctx_with(cfg), &parts), "");
+    }
+
+    #[test]
+    fn same_track_keeps_its_hash_across_events() {
+        let playing = "Playing|spotify|Song|Band|180000000|https://a/1.jpg|1000000|Album||spotify:track:1";
+        let seeked = "Paused|spotify|Song|Band|180000000|https://a/1.jpg|95000000|Album||spotify:track:1";
+        let hash = |line: &str| {
+            let p: Vec<&str> = line.splitn(METADATA_FIELDS, '|').collect();
+            track_hash(p[2], p[3], p[7], p[4])
+        };
+        assert_eq!(hash(playing), hash(seeked));
+        assert_eq!(hash(playing).len(), 12);
+        assert_ne!(hash(playing), hash(&playing.replace("Song", "Other")));
+        assert_eq!(track_hash("", "Band", "", ""), "");
+    }
+
+    #[test]
+    fn can_control_uses_the_followed_players_state() {
+        let ctx = test_ctx();
+        let mut st = UiState::empty("");
+        st.name = "spotify".into();
+        st.can_control = 0;
+        *ctx.last_state.write().unwrap() = Some(st);
+        assert!(!can_control_sync(&ctx, "spotify"));
+    }
+
+    #[test]
+    fn ping_answers_without_players() {
+        let ctx = test_ctx();
+        let cmd = serde_json::from_str::<IpcCmd>(r#"{"cmd":"ping"}"#).unwrap();
+        assert_eq!(dispatch_ipc(&Arc::new(ctx), cmd).unwrap(), serde_json::json!({"pong": true}));
+    }
+
+    #[tokio::test]
+    async fn follower_lines_survive_invalid_utf8() {
+        let input: &[u8] = b"Playing|spotify|Caf\xe9 Song|Artist|1|||\nPlaying|spotify|Next|Artist|1|||\n";
+        let mut reader = input;
+        let mut buf = Vec::new();
+        let first = next_line_lossy(&mut reader, &mut buf).await.unwrap().unwrap();
+        assert_eq!(first.split('|').nth(2), Some("Caf\u{fffd} Song"));
+        // The stream goes on after the bad line
+        let second = next_line_lossy(&mut reader, &mut buf).await.unwrap().unwrap();
+        assert_eq!(second.split('|').nth(2), Some("Next"));
+        assert!(next_line_lossy(&mut reader, &mut buf).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn require_audio_prefers_audible_player() {
+        let pactl = r#"Sink Input #41
+	Corked: no
+	Mute: no
+	Properties:
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+Sink Input #42
+	Corked: yes
+	Mute: no
+	Properties:
+		application.name = "Spotify"
+"#;
+        let mut cfg = Config::default();
+        cfg.selection.require_audio = true;
+        cfg.selection.priority = vec!["spotify".into(), "firefox".into()];
+        let ctx = ctx_with(cfg);
+        *ctx.audible_apps.write().unwrap() = Some(parse_audible_apps(pactl));
+        // spotify wins on priority alone, but its stream is corked
+        add_player(&ctx, "spotify", "Playing");
+        add_player(&ctx, "firefox.instance_1_240", "Playing");
+        assert_eq!(recompute_selected(&ctx).map(|(p, _)| p).as_deref(), Some("firefox.instance_1_240"));
+    }
+}